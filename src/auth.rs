@@ -5,31 +5,79 @@ use std::{
         Arc,
         Mutex
     },
-    collections::HashMap, process::exit,
+    collections::{HashMap, HashSet},
+    fs,
+    net::TcpListener,
+    path::PathBuf,
+    time::Duration,
 };
 use inquire::InquireError;
 use url::Url;
 use rspotify::{
     prelude::*,
-    scopes,
     AuthCodePkceSpotify,
     Credentials,
     OAuth,
+    Token,
     ClientError,
 };
 use rocket;
-use crate::config::{Config, app_config_dir};
+use crate::{config::{Config, app_config_dir}, hook};
 use arboard::Clipboard;
 
 
-const CLIENT_ID: &str = "fa974cd060ed42888385234c45c531bb";
 const TOKEN_CACHE_FILE: &str = ".spotify_token_cache.json";
+/// The client id the cached token was issued to.
+const TOKEN_CLIENT_ID_FILE: &str = ".spotify_token_client_id";
 
-const SCOPES: [&str; 5] = [
-    "user-library-read",
-    "user-read-currently-playing",
+const CALLBACK_PAGE: &str = r#"<!DOCTYPE html>
+<html>
+<head>
+    <meta charset="utf-8">
+    <title>visify</title>
+    <style>
+        body {
+            font-family: sans-serif;
+            background: #1b1b1b;
+            color: #e0e0e0;
+            display: flex;
+            align-items: center;
+            justify-content: center;
+            height: 100vh;
+            margin: 0;
+        }
+        h1 { color: #c42727; }
+    </style>
+</head>
+<body>
+    <div>
+        <h1>Authorization succeeded</h1>
+        <p>You can close this tab and return to visify.</p>
+    </div>
+    <script>window.close();</script>
+</body>
+</html>
+"#;
+
+const KNOWN_SCOPES: [&str; 19] = [
+    "ugc-image-upload",
     "user-read-playback-state",
+    "user-modify-playback-state",
+    "user-read-currently-playing",
+    "app-remote-control",
+    "streaming",
+    "playlist-read-private",
+    "playlist-read-collaborative",
+    "playlist-modify-private",
+    "playlist-modify-public",
+    "user-follow-modify",
+    "user-follow-read",
     "user-read-playback-position",
+    "user-top-read",
+    "user-read-recently-played",
+    "user-library-modify",
+    "user-library-read",
+    "user-read-email",
     "user-read-private",
 ];
 
@@ -47,6 +95,20 @@ pub enum Error {
     UrlMissingParam(String),
     #[error("Invalid menu result: {0}")]
     MenuResult(String),
+    #[error("The provided refresh token was rejected: {0}")]
+    RefreshToken(ClientError),
+    #[error("The provided refresh token did not yield an access token")]
+    MissingRefreshedToken,
+    #[error("Unknown spotify scope in config: {0}")]
+    UnknownScope(String),
+    #[error("Callback server failed: {0}")]
+    Rocket(Box<rocket::Error>),
+    #[error("No login came back within {0} seconds")]
+    CallbackTimeout(u64),
+    #[error("Could not determine the config directory to keep the token in")]
+    NoConfigDir,
+    #[error("Port {0} for the login redirect is already in use. Stop whatever holds it, or set redirect_uri_port to another port registered with the Spotify app")]
+    PortInUse(u16),
 }
 
 pub type Result<T> = result::Result<T, Error>;
@@ -56,39 +118,75 @@ struct TokenRetriever {
     code: String
 }
 
+/// The page shown in the browser once the login came back.
+struct CallbackPage(String);
+
+fn callback_page(config: &Config) -> String {
+    match &config.callback_page {
+        Some(path) => fs::read_to_string(path).unwrap_or_else(|error| {
+            eprintln!("Failed to read custom callback page {}: {error}", path.display());
+            CALLBACK_PAGE.to_string()
+        }),
+        None => CALLBACK_PAGE.to_string(),
+    }
+}
+
 #[rocket::get("/callback?<code>")]
 fn callback(
     code: String,
     token_retriever: &rocket::State<Arc<Mutex<TokenRetriever>>>,
+    page: &rocket::State<CallbackPage>,
     shutdown: rocket::Shutdown
-) -> String {
+) -> rocket::response::content::RawHtml<String> {
     shutdown.notify();
     
     let mut token_retriever = token_retriever.lock().unwrap();
     token_retriever.code = code;
 
-    "success!".to_string()
+    rocket::response::content::RawHtml(page.0.clone())
 }
 
-async fn redirect_uri_web_server() -> result::Result<String, rocket::Error> {
+/// Gives up waiting on the browser after `callback_timeout_secs`, e.g. when
+/// the login page was closed without authorizing.
+async fn redirect_uri_web_server(config: &Config) -> Result<String> {
     let token_retriever = Arc::new(Mutex::new(TokenRetriever::default()));
     let rocket_config = rocket::Config {
-        port: redirect_uri_port(),
+        address: config.redirect_bind_address.unwrap(),
+        port: config.redirect_uri_port.unwrap(),
         ..Default::default()
     };
 
-    let _ = rocket::custom(&rocket_config)
+    let server = rocket::custom(&rocket_config)
         .manage(Arc::clone(&token_retriever))
+        .manage(CallbackPage(callback_page(config)))
         .mount("/", rocket::routes![callback])
-        .launch()
-        .await?;
+        .launch();
+    let timeout_secs = config.callback_timeout_secs.unwrap();
+    match tokio::time::timeout(Duration::from_secs(timeout_secs), server).await {
+        Ok(launched) => launched.map_err(|error| Error::Rocket(Box::new(error)))?,
+        Err(_) => return Err(Error::CallbackTimeout(timeout_secs)),
+    };
 
     let code = token_retriever.lock().unwrap().code.clone();
 
     Ok(code)
 }
 
-async fn get_code(url: &str) -> Result<String> {
+/// Checked before opening the browser, since rocket only fails once the
+/// login is underway. Another free port won't do, Spotify only redirects to
+/// the registered one.
+fn check_redirect_port(config: &Config) -> Result<()> {
+    let port = config.redirect_uri_port.unwrap();
+    match TcpListener::bind((config.redirect_bind_address.unwrap(), port)) {
+        Ok(_) => Ok(()),
+        Err(error) if error.kind() == io::ErrorKind::AddrInUse => Err(Error::PortInUse(port)),
+        Err(error) => Err(error.into()),
+    }
+}
+
+async fn get_code(url: &str, config: &Config) -> Result<String> {
+    check_redirect_port(config)?;
+
     let mut clipboard = Clipboard::new().unwrap();
     clipboard.set_text(url).unwrap();
 
@@ -100,7 +198,7 @@ async fn get_code(url: &str) -> Result<String> {
         Please navigate to login page manually (login URL already copied to cpliboard): {url}")
     }
 
-    let maybe_code = redirect_uri_web_server().await;
+    let maybe_code = redirect_uri_web_server(config).await;
 
     match maybe_code {
         Ok(code) => Ok(code),
@@ -124,7 +222,7 @@ async fn get_code(url: &str) -> Result<String> {
     }
 }
 
-async fn get_token(client: &mut AuthCodePkceSpotify, auth_url: &str) -> Result<()> {
+async fn get_token(client: &mut AuthCodePkceSpotify, auth_url: &str, config: &Config) -> Result<()> {
     match client.read_token_cache(true).await {
         Ok(Some(new_token)) => {
             let expired = new_token.is_expired();
@@ -141,7 +239,7 @@ async fn get_token(client: &mut AuthCodePkceSpotify, auth_url: &str) -> Result<(
                     }
                     // If not, prompt the user for it
                     None => {
-                        let code = get_code(auth_url).await?;
+                        let code = get_code(auth_url, config).await?;
                         client.request_token(&code).await?;
                     }
                 }
@@ -149,7 +247,7 @@ async fn get_token(client: &mut AuthCodePkceSpotify, auth_url: &str) -> Result<(
         }
         // Otherwise following the usual procedure to get the token.
         _ => {
-            let code = get_code(auth_url).await?;
+            let code = get_code(auth_url, config).await?;
             client.request_token(&code).await?;
         }
     }
@@ -157,35 +255,119 @@ async fn get_token(client: &mut AuthCodePkceSpotify, auth_url: &str) -> Result<(
     Ok(client.write_token_cache().await?)
 }
 
-fn redirect_uri_port() -> u16 {
-    match Config::load() {
-        Ok(config) => config.redirect_uri_port.unwrap(),
-        Err(error) => {
-            eprintln!("Failed to load redirect uri port from config: {error}.");
-            exit(1)
+async fn seed_refresh_token(client: &AuthCodePkceSpotify, refresh_token: String) -> Result<()> {
+    let seed = Token {
+        refresh_token: Some(refresh_token),
+        ..Default::default()
+    };
+    *client.get_token().lock().await.unwrap() = Some(seed);
+
+    match client.refetch_token().await.map_err(Error::RefreshToken)? {
+        Some(refreshed_token) => {
+            *client.get_token().lock().await.unwrap() = Some(refreshed_token);
+        }
+        None => return Err(Error::MissingRefreshedToken),
+    }
+
+    Ok(client.write_token_cache().await?)
+}
+
+/// Refreshing rotates the refresh token, so after the first start only the
+/// cache holds a live one. Returns whether it did.
+async fn refresh_cached_token(client: &AuthCodePkceSpotify) -> Result<bool> {
+    let Ok(Some(token)) = client.read_token_cache(true).await else {
+        return Ok(false);
+    };
+    let expired = token.is_expired();
+    *client.get_token().lock().await.unwrap() = Some(token);
+
+    if expired {
+        match client.refetch_token().await {
+            Ok(Some(refreshed_token)) => *client.get_token().lock().await.unwrap() = Some(refreshed_token),
+            _ => return Ok(false),
         }
     }
+
+    client.write_token_cache().await?;
+    Ok(true)
+}
+
+fn scopes(config: &Config) -> Result<HashSet<String>> {
+    let scopes = config.scopes.clone().unwrap();
+
+    match scopes.iter().find(|scope| !KNOWN_SCOPES.contains(&scope.as_str())) {
+        Some(unknown_scope) => Err(Error::UnknownScope(unknown_scope.clone())),
+        None => Ok(scopes.into_iter().collect()),
+    }
+}
+
+/// Stays on localhost even when binding elsewhere, since that's what the
+/// app registered with Spotify.
+fn redirect_uri(config: &Config) -> String {
+    format!("http://localhost:{}/callback", config.redirect_uri_port.unwrap())
+}
+
+fn config_dir() -> Result<PathBuf> {
+    app_config_dir().ok_or(Error::NoConfigDir)
 }
 
-fn redirect_uri() -> String {
-    format!("http://localhost:{}/callback", redirect_uri_port())
+/// Tokens only work with the client id they were issued to, so a cached one
+/// from before `client_id` changed is deleted.
+fn forget_token_of_other_client(client_id: &str) -> Result<()> {
+    let config_dir = config_dir()?;
+    let path = config_dir.join(TOKEN_CLIENT_ID_FILE);
+    match fs::read_to_string(&path) {
+        Ok(cached_client_id) if cached_client_id == client_id => return Ok(()),
+        Ok(_) => {
+            println!("The client id changed, logging in again.");
+            logout()?;
+        }
+        Err(error) if error.kind() == io::ErrorKind::NotFound => (),
+        Err(error) => return Err(error.into()),
+    }
+    fs::create_dir_all(config_dir)?;
+    Ok(fs::write(path, client_id)?)
 }
 
-pub async fn auth() -> Result<AuthCodePkceSpotify>{
-    let creds = Credentials::new_pkce(CLIENT_ID);
+pub async fn auth(config: &Config) -> Result<AuthCodePkceSpotify>{
+    let client_id = config.client_id.clone().unwrap();
+    forget_token_of_other_client(&client_id)?;
+    let creds = Credentials::new_pkce(&client_id);
 
     let oauth = OAuth {
-        redirect_uri: redirect_uri(),
-        scopes: scopes!(&SCOPES.join(" ")),
+        redirect_uri: redirect_uri(config),
+        scopes: scopes(config)?,
         ..Default::default()
     };
 
     let mut spotify = AuthCodePkceSpotify::new(creds.clone(), oauth.clone());
     spotify.config.token_cached = true;
-    spotify.config.cache_path = app_config_dir().join(TOKEN_CACHE_FILE);
+    spotify.config.cache_path = config_dir()?.join(TOKEN_CACHE_FILE);
+
+    // A pre-obtained refresh token skips the interactive browser flow, and
+    // is only needed when the cached token can't be refreshed.
+    if let Some(refresh_token) = config.refresh_token.clone() {
+        if !refresh_cached_token(&spotify).await? {
+            seed_refresh_token(&spotify, refresh_token).await?;
+        }
+    } else {
+        let auth_url = spotify.get_authorize_url(None)?;
+        let _ = get_token(&mut spotify, &auth_url, config).await?;
+    }
+
+    if let Some(command) = &config.on_auth_success {
+        let token_cache = spotify.config.cache_path.display().to_string();
+        hook::run(command, vec![("VISIFY_TOKEN_CACHE", token_cache)], None);
+    }
 
-    let auth_url = spotify.get_authorize_url(None)?;
-    let _ = get_token(&mut spotify, &auth_url).await?;
-    
     Ok(spotify)
 }
+
+/// Deletes the cached token, so the next start logs in from scratch, e.g.
+/// with another account. Not being logged in is fine too.
+pub fn logout() -> Result<()> {
+    match fs::remove_file(config_dir()?.join(TOKEN_CACHE_FILE)) {
+        Err(error) if error.kind() != io::ErrorKind::NotFound => Err(error.into()),
+        _ => Ok(()),
+    }
+}
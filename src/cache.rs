@@ -0,0 +1,157 @@
+use std::{
+    fs,
+    io,
+    result,
+    path::{Path, PathBuf},
+    time::SystemTime,
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use eframe::egui::{self, load::{ImageLoader, ImageLoadResult, ImagePoll, LoadError, SizeHint}, ColorImage};
+
+#[derive(thiserror::Error, Debug)]
+pub enum CacheError {
+    #[error("i/o error: {0}")]
+    Io(#[from] io::Error),
+    #[error("Failed to download cover art: {0}")]
+    Http(#[from] reqwest::Error),
+    #[error("Cache task failed: {0}")]
+    Join(#[from] tokio::task::JoinError),
+}
+
+pub type CacheResult<T> = result::Result<T, CacheError>;
+
+pub struct CoverCache {
+    dir: PathBuf,
+    max_bytes: u64,
+    http: reqwest::Client,
+}
+
+/// 64-bit FNV-1a of `url`. Unlike std's hasher it stays the same across Rust
+/// releases, so cached files are still found after a toolchain upgrade.
+pub fn cache_key(url: &str) -> String {
+    let hash = url.bytes().fold(0xcbf29ce484222325_u64, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    });
+    format!("{hash:016x}")
+}
+
+/// Deletes the least recently used files in `dir` until it fits in
+/// `max_bytes`, sparing `keep`, the file that was just added.
+pub fn evict(dir: &Path, max_bytes: u64, keep: &Path) -> io::Result<()> {
+    let mut entries = fs::read_dir(dir)?
+        .filter_map(|entry| {
+            let entry = entry.ok()?;
+            let metadata = entry.metadata().ok()?;
+            Some((entry.path(), metadata.len(), metadata.modified().ok()?))
+        })
+        .collect::<Vec<_>>();
+    entries.sort_by_key(|(_, _, modified)| *modified);
+
+    let mut total: u64 = entries.iter().map(|(_, len, _)| len).sum();
+    for (path, len, _) in entries {
+        if total <= max_bytes {
+            break;
+        }
+        if path == keep {
+            continue;
+        }
+        fs::remove_file(path)?;
+        total -= len;
+    }
+
+    Ok(())
+}
+
+impl CoverCache {
+    pub fn new(dir: PathBuf, max_bytes: u64) -> Self {
+        Self {
+            dir,
+            max_bytes,
+            http: reqwest::Client::new(),
+        }
+    }
+
+    /// Returns the on-disk path of the image at `url`, downloading it first
+    /// if it isn't cached yet.
+    pub async fn fetch(&self, url: &str) -> CacheResult<PathBuf> {
+        let path = self.dir.join(cache_key(url));
+
+        if tokio::fs::try_exists(&path).await? {
+            // The modification time doubles as the last-used time for eviction.
+            let touched = path.clone();
+            tokio::task::spawn_blocking(move || fs::File::options().append(true).open(touched)?.set_modified(SystemTime::now())).await??;
+            return Ok(path);
+        }
+
+        let bytes = self.http.get(url).send().await?.error_for_status()?.bytes().await?;
+        tokio::fs::create_dir_all(&self.dir).await?;
+        tokio::fs::write(&path, &bytes).await?;
+
+        let (dir, max_bytes, fresh) = (self.dir.clone(), self.max_bytes, path.clone());
+        tokio::task::spawn_blocking(move || evict(&dir, max_bytes, &fresh)).await??;
+        Ok(path)
+    }
+}
+
+/// Decodes cover art straight from the on-disk cache, so no other loader has
+/// to be installed. Anything outside the cache directory is left to others.
+pub struct CoverCacheLoader {
+    dir: PathBuf,
+    images: Mutex<HashMap<String, Result<Arc<ColorImage>, String>>>,
+}
+
+impl CoverCacheLoader {
+    pub const ID: &str = concat!(module_path!(), "::CoverCacheLoader");
+
+    pub fn new(dir: PathBuf) -> Self {
+        Self {
+            dir,
+            images: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl ImageLoader for CoverCacheLoader {
+    fn id(&self) -> &str {
+        Self::ID
+    }
+
+    fn load(&self, _ctx: &egui::Context, uri: &str, _size_hint: SizeHint) -> ImageLoadResult {
+        let Some(path) = uri.strip_prefix("file://").map(Path::new).filter(|path| path.starts_with(&self.dir)) else {
+            return Err(LoadError::NotSupported);
+        };
+
+        let mut images = self.images.lock().unwrap();
+        let image = images.entry(uri.to_owned()).or_insert_with(|| {
+            let bytes = fs::read(path).map_err(|error| error.to_string())?;
+            egui_extras::image::load_image_bytes(&bytes).map(Arc::new)
+        });
+
+        match image {
+            Ok(image) => Ok(ImagePoll::Ready { image: image.clone() }),
+            Err(error) => Err(LoadError::Loading(error.clone())),
+        }
+    }
+
+    fn forget(&self, uri: &str) {
+        self.images.lock().unwrap().remove(uri);
+    }
+
+    fn forget_all(&self) {
+        self.images.lock().unwrap().clear();
+    }
+
+    fn byte_size(&self) -> usize {
+        self.images
+            .lock()
+            .unwrap()
+            .values()
+            .map(|image| match image {
+                Ok(image) => image.pixels.len() * std::mem::size_of::<egui::Color32>(),
+                Err(error) => error.len(),
+            })
+            .sum()
+    }
+}
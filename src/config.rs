@@ -1,32 +1,534 @@
-use std::path::{PathBuf, Path};
+use std::{path::{PathBuf, Path}, env, str::FromStr, collections::HashMap, net::{IpAddr, Ipv4Addr}};
 
+use directories::ProjectDirs;
 use serde::{Serialize, Deserialize};
 
 const CONFIG_DIR: &str = ".config";
 const APP_NAME: &str = "visify";
 const CONFIG_NAME: &str = "config";
+const DEFAULT_CLIENT_ID: &str = "fa974cd060ed42888385234c45c531bb";
 const DEFAULT_REDIRECT_URI_PORT: u16 = 8888;
+const DEFAULT_CALLBACK_TIMEOUT_SECS: u64 = 120;
+const CLIENT_ID_ENV: &str = "VISIFY_CLIENT_ID";
+const REDIRECT_PORT_ENV: &str = "VISIFY_REDIRECT_PORT";
+const REFRESH_TOKEN_ENV: &str = "VISIFY_REFRESH_TOKEN";
+const DEFAULT_SCOPES: [&str; 9] = [
+    "user-library-read",
+    "user-follow-read",
+    "user-follow-modify",
+    "user-read-currently-playing",
+    "user-read-playback-state",
+    "user-read-playback-position",
+    "user-read-private",
+    "user-modify-playback-state",
+    "user-library-modify",
+];
+const DEFAULT_REFERENCE_PITCH: f64 = 440.0;
+const DEFAULT_SPECTRUM_BRIGHTNESS: f32 = 1.0;
+const DEFAULT_SPECTRUM_CONTRAST: f32 = 1.0;
+const DEFAULT_COVER_CACHE_MAX_MB: u64 = 50;
+const DEFAULT_SILENCE_THRESHOLD: f32 = 1e-4;
+const DEFAULT_WINDOW_OPACITY: f32 = 1.0;
+const DEFAULT_ANALYSIS_RATE_HZ: f64 = 30.0;
+const DEFAULT_MAX_INTERPOLATION_MS: u64 = 10_000;
+const DEFAULT_PAUSE_DIM: f32 = 0.4;
+const DEFAULT_POINT_RADIUS: f32 = 2.0;
+const DEFAULT_SMOOTHING: f64 = 0.84;
+const DEFAULT_GAIN: f64 = 5000.0;
+const DEFAULT_MAX_FREQUENCY: f32 = 10000.0;
+/// Just short of 1.0, where the spectrum would never decay at all.
+const MAX_SMOOTHING: f64 = 0.99;
+const DEFAULT_BAR_COUNT: usize = 32;
+const DEFAULT_BUFFER_SECONDS: f32 = 5.0;
+const DEFAULT_IDLE_GRACE_MS: u64 = 5000;
+const DEFAULT_REFRESH_RATE_MS: u64 = 5000;
+/// Floor for polling Spotify, so a typo can't run into its rate limits.
+pub const MIN_REFRESH_RATE_MS: u64 = 500;
+const DEFAULT_ONSET_SENSITIVITY: f64 = 1.5;
+const DEFAULT_FLASH_INTENSITY: f32 = 0.3;
+const DEFAULT_ARTIST_SEPARATOR: &str = ", ";
+const DEFAULT_GIVE_UP_MESSAGE: &str = "Lost connection to Spotify — press R to retry";
 
-#[derive(Serialize, Deserialize, Debug, Default)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum BandSpacing {
+    Logarithmic,
+    MusicalNotes,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum ProgressStyle {
+    Bar,
+    Ring,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum LayoutMode {
+    Full,
+    MiniProgress,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum Appearance {
+    /// Follows the OS where it reports a preference, dark elsewhere.
+    Auto,
+    Dark,
+    Light,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum Orientation {
+    /// Portrait whenever the window is taller than it is wide.
+    Auto,
+    Landscape,
+    Portrait,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum FitMode {
+    Contain,
+    Cover,
+    Stretch,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum FreqAxis {
+    Log,
+    Linear,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum SpectrumStyle {
+    Line,
+    Points,
+    /// `bar_count` bars, equally wide along the frequency axis.
+    Bars,
+}
+
+/// Colors as hex strings like `#c42727`. Missing or invalid ones keep the
+/// built-in colors.
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct Theme {
+    /// Icons that are on, e.g. liked or shuffled.
+    pub accent: Option<String>,
+    /// Icons that are off.
+    pub accent_inactive: Option<String>,
+    /// The progress bar or ring, also used for the spectrum.
+    pub progress_fill: Option<String>,
+}
+
+/// `CacheOnly` loads cover art from the cover cache and nothing else.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum ImageLoaders {
+    All,
+    CacheOnly,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum MarqueeMode {
+    Always,
+    OnHover,
+    Off,
+}
+
+/// A named bundle of spectrum settings. Anything left out keeps the value
+/// from the top level of the config.
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct SpectrumSettings {
+    pub band_spacing: Option<BandSpacing>,
+    pub reference_pitch: Option<f64>,
+    pub spectrum_brightness: Option<f32>,
+    pub spectrum_contrast: Option<f32>,
+    pub silence_threshold: Option<f32>,
+    pub gravity: Option<f64>,
+    pub smoothing: Option<f64>,
+    pub gain: Option<f64>,
+    pub invert_spectrum: Option<bool>,
+    pub interpolate_spectrum: Option<bool>,
+    pub analysis_rate_hz: Option<f64>,
+}
+
+/// `Default` leaves every setting unset, which is also what confy writes to a
+/// missing file, so built-in defaults never get frozen into it.
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
 pub struct Config {
+    /// The Spotify app to log in with, e.g. one registered with other
+    /// redirect URIs. Changing it deletes the cached token, which only works
+    /// with the app that issued it.
+    pub client_id: Option<String>,
     pub redirect_uri_port: Option<u16>,
+    pub redirect_bind_address: Option<IpAddr>,
+    pub refresh_rate_ms: Option<u64>,
+    pub band_spacing: Option<BandSpacing>,
+    pub reference_pitch: Option<f64>,
+    pub spectrum_brightness: Option<f32>,
+    pub spectrum_contrast: Option<f32>,
+    pub cover_cache_max_mb: Option<u64>,
+    pub silence_threshold: Option<f32>,
+    pub progress_style: Option<ProgressStyle>,
+    pub refresh_token: Option<String>,
+    pub gravity: Option<f64>,
+    /// How much of the previous frame each spectrum bin keeps, in `[0, 1)`.
+    /// Higher decays slower, 0 follows the audio instantly. Unused with
+    /// `gravity` set.
+    pub smoothing: Option<f64>,
+    /// Scales every bin before plotting, raise it for quiet inputs.
+    pub gain: Option<f64>,
+    /// Highest frequency analyzed and shown, at most half the sample rate.
+    pub max_frequency: Option<f32>,
+    pub callback_page: Option<PathBuf>,
+    pub callback_timeout_secs: Option<u64>,
+    pub scopes: Option<Vec<String>>,
+    pub decorated: Option<bool>,
+    pub appearance: Option<Appearance>,
+    pub window_opacity: Option<f32>,
+    pub msaa_samples: Option<u16>,
+    pub vsync: Option<bool>,
+    pub on_change_command: Option<String>,
+    pub on_auth_success: Option<String>,
+    pub invert_spectrum: Option<bool>,
+    pub layout: Option<LayoutMode>,
+    pub orientation: Option<Orientation>,
+    pub playing_track_fallback: Option<bool>,
+    pub lyrics_dir: Option<PathBuf>,
+    pub interpolate_spectrum: Option<bool>,
+    pub analysis_rate_hz: Option<f64>,
+    pub fit_mode: Option<FitMode>,
+    pub screensaver_idle_secs: Option<u64>,
+    pub max_interpolation_ms: Option<u64>,
+    pub marquee_mode: Option<MarqueeMode>,
+    pub capture_sample_rate: Option<u32>,
+    pub audio_device: Option<String>,
+    pub input_devices: Option<Vec<String>>,
+    pub buffer_seconds: Option<f32>,
+    pub compact_icons: Option<bool>,
+    pub presets: Option<HashMap<String, SpectrumSettings>>,
+    pub dim_on_pause: Option<bool>,
+    pub pause_dim: Option<f32>,
+    pub normalization_exclude_below: Option<f64>,
+    pub normalization_exclude_above: Option<f64>,
+    /// Scales the spectrum by its recent peak instead of a fixed `gain`.
+    pub auto_gain: Option<bool>,
+    pub theme: Option<Theme>,
+    pub beat_flash: Option<bool>,
+    pub onset_sensitivity: Option<f64>,
+    pub flash_intensity: Option<f32>,
+    pub image_loaders: Option<ImageLoaders>,
+    pub max_retries: Option<u32>,
+    pub idle_grace_ms: Option<u64>,
+    pub give_up_message: Option<String>,
+    pub auto_preset: Option<bool>,
+    pub show_tempo: Option<bool>,
+    pub metronome: Option<bool>,
+    pub spectrum_style: Option<SpectrumStyle>,
+    pub point_radius: Option<f32>,
+    pub bar_count: Option<usize>,
+    pub progress_offset_ms: Option<i64>,
+    pub focus_mode: Option<bool>,
+    pub show_header: Option<bool>,
+    pub ambient_glow: Option<bool>,
+    /// Shows Spotify's icon, read from `spotify-icon.png` in the config
+    /// directory, or its name when the file isn't there.
+    pub show_attribution: Option<bool>,
+    pub artist_separator: Option<String>,
+    pub primary_artist_only: Option<bool>,
+    pub freq_axis: Option<FreqAxis>,
+    pub spectrum_tilt_db_per_octave: Option<f64>,
 }
 
 impl Config {
+    /// The one place defining what every setting defaults to. Settings without
+    /// a sensible default, like `gravity` or `lyrics_dir`, stay off as `None`.
+    pub fn defaults() -> Config {
+        Config {
+            client_id: Some(DEFAULT_CLIENT_ID.to_string()),
+            redirect_uri_port: Some(DEFAULT_REDIRECT_URI_PORT),
+            redirect_bind_address: Some(IpAddr::V4(Ipv4Addr::LOCALHOST)),
+            refresh_rate_ms: Some(DEFAULT_REFRESH_RATE_MS),
+            band_spacing: None,
+            reference_pitch: Some(DEFAULT_REFERENCE_PITCH),
+            spectrum_brightness: Some(DEFAULT_SPECTRUM_BRIGHTNESS),
+            spectrum_contrast: Some(DEFAULT_SPECTRUM_CONTRAST),
+            cover_cache_max_mb: Some(DEFAULT_COVER_CACHE_MAX_MB),
+            silence_threshold: Some(DEFAULT_SILENCE_THRESHOLD),
+            progress_style: Some(ProgressStyle::Bar),
+            refresh_token: None,
+            gravity: None,
+            smoothing: Some(DEFAULT_SMOOTHING),
+            gain: Some(DEFAULT_GAIN),
+            max_frequency: Some(DEFAULT_MAX_FREQUENCY),
+            callback_page: None,
+            callback_timeout_secs: Some(DEFAULT_CALLBACK_TIMEOUT_SECS),
+            scopes: Some(DEFAULT_SCOPES.map(String::from).to_vec()),
+            decorated: Some(true),
+            appearance: Some(Appearance::Auto),
+            window_opacity: Some(DEFAULT_WINDOW_OPACITY),
+            msaa_samples: Some(0),
+            vsync: Some(true),
+            on_change_command: None,
+            on_auth_success: None,
+            invert_spectrum: Some(false),
+            layout: Some(LayoutMode::Full),
+            orientation: Some(Orientation::Auto),
+            playing_track_fallback: Some(false),
+            lyrics_dir: None,
+            interpolate_spectrum: Some(false),
+            analysis_rate_hz: Some(DEFAULT_ANALYSIS_RATE_HZ),
+            fit_mode: Some(FitMode::Contain),
+            screensaver_idle_secs: None,
+            max_interpolation_ms: Some(DEFAULT_MAX_INTERPOLATION_MS),
+            marquee_mode: Some(MarqueeMode::Off),
+            capture_sample_rate: None,
+            audio_device: None,
+            input_devices: None,
+            buffer_seconds: Some(DEFAULT_BUFFER_SECONDS),
+            compact_icons: Some(false),
+            presets: None,
+            dim_on_pause: Some(false),
+            pause_dim: Some(DEFAULT_PAUSE_DIM),
+            normalization_exclude_below: None,
+            normalization_exclude_above: None,
+            auto_gain: Some(false),
+            theme: None,
+            beat_flash: Some(false),
+            onset_sensitivity: Some(DEFAULT_ONSET_SENSITIVITY),
+            flash_intensity: Some(DEFAULT_FLASH_INTENSITY),
+            image_loaders: Some(ImageLoaders::All),
+            max_retries: None,
+            idle_grace_ms: Some(DEFAULT_IDLE_GRACE_MS),
+            give_up_message: Some(DEFAULT_GIVE_UP_MESSAGE.to_string()),
+            auto_preset: Some(false),
+            show_tempo: Some(false),
+            metronome: Some(false),
+            spectrum_style: Some(SpectrumStyle::Line),
+            point_radius: Some(DEFAULT_POINT_RADIUS),
+            bar_count: Some(DEFAULT_BAR_COUNT),
+            progress_offset_ms: Some(0),
+            focus_mode: Some(false),
+            show_header: Some(false),
+            ambient_glow: Some(false),
+            show_attribution: Some(false),
+            artist_separator: Some(DEFAULT_ARTIST_SEPARATOR.to_string()),
+            primary_artist_only: Some(false),
+            freq_axis: Some(FreqAxis::Log),
+            spectrum_tilt_db_per_octave: Some(0.0),
+        }
+    }
+
     pub fn load() -> Result<Config, confy::ConfyError> {
-        let mut config: Config = confy::load(APP_NAME, CONFIG_NAME)?;
+        Config::load_from(confy::get_configuration_file_path(APP_NAME, CONFIG_NAME)?)
+    }
 
-        config.redirect_uri_port = Some(config.redirect_uri_port.unwrap_or(DEFAULT_REDIRECT_URI_PORT));
+    /// Loads the config file at `path` instead of the user's, filling in
+    /// defaults the same way.
+    pub fn load_from(path: impl AsRef<Path>) -> Result<Config, confy::ConfyError> {
+        let stored: Config = confy::load_path(path)?;
+        let mut config = stored.or(Config::defaults());
+
+        config.client_id = resolve(CLIENT_ID_ENV, config.client_id);
+        config.redirect_uri_port = resolve(REDIRECT_PORT_ENV, config.redirect_uri_port);
+        config.refresh_token = resolve(REFRESH_TOKEN_ENV, config.refresh_token);
+        config.window_opacity = config.window_opacity.map(|opacity| opacity.clamp(0.0, 1.0));
+        config.analysis_rate_hz = config.analysis_rate_hz.map(|rate| rate.max(1.0));
+        config.bar_count = config.bar_count.map(|count| count.max(1));
+        config.smoothing = config.smoothing.map(clamp_smoothing);
+        config.gain = config.gain.map(|gain| gain.max(0.0));
+        config.refresh_rate_ms = config.refresh_rate_ms.map(|rate| rate.max(MIN_REFRESH_RATE_MS));
+        config.onset_sensitivity = config.onset_sensitivity.map(|sensitivity| sensitivity.max(1.0));
+        config.flash_intensity = config.flash_intensity.map(|intensity| intensity.clamp(0.0, 1.0));
+        config.pause_dim = config.pause_dim.map(|dim| dim.clamp(0.0, 1.0));
         Ok(config)
     }
 
+    /// Fills every setting missing from `self` with the one from `defaults`.
+    fn or(self, defaults: Config) -> Config {
+        Config {
+            client_id: self.client_id.or(defaults.client_id),
+            redirect_uri_port: self.redirect_uri_port.or(defaults.redirect_uri_port),
+            redirect_bind_address: self.redirect_bind_address.or(defaults.redirect_bind_address),
+            refresh_rate_ms: self.refresh_rate_ms.or(defaults.refresh_rate_ms),
+            band_spacing: self.band_spacing.or(defaults.band_spacing),
+            reference_pitch: self.reference_pitch.or(defaults.reference_pitch),
+            spectrum_brightness: self.spectrum_brightness.or(defaults.spectrum_brightness),
+            spectrum_contrast: self.spectrum_contrast.or(defaults.spectrum_contrast),
+            cover_cache_max_mb: self.cover_cache_max_mb.or(defaults.cover_cache_max_mb),
+            silence_threshold: self.silence_threshold.or(defaults.silence_threshold),
+            progress_style: self.progress_style.or(defaults.progress_style),
+            refresh_token: self.refresh_token.or(defaults.refresh_token),
+            gravity: self.gravity.or(defaults.gravity),
+            smoothing: self.smoothing.or(defaults.smoothing),
+            gain: self.gain.or(defaults.gain),
+            max_frequency: self.max_frequency.or(defaults.max_frequency),
+            callback_page: self.callback_page.or(defaults.callback_page),
+            callback_timeout_secs: self.callback_timeout_secs.or(defaults.callback_timeout_secs),
+            scopes: self.scopes.or(defaults.scopes),
+            decorated: self.decorated.or(defaults.decorated),
+            appearance: self.appearance.or(defaults.appearance),
+            window_opacity: self.window_opacity.or(defaults.window_opacity),
+            msaa_samples: self.msaa_samples.or(defaults.msaa_samples),
+            vsync: self.vsync.or(defaults.vsync),
+            on_change_command: self.on_change_command.or(defaults.on_change_command),
+            on_auth_success: self.on_auth_success.or(defaults.on_auth_success),
+            invert_spectrum: self.invert_spectrum.or(defaults.invert_spectrum),
+            layout: self.layout.or(defaults.layout),
+            orientation: self.orientation.or(defaults.orientation),
+            playing_track_fallback: self.playing_track_fallback.or(defaults.playing_track_fallback),
+            lyrics_dir: self.lyrics_dir.or(defaults.lyrics_dir),
+            interpolate_spectrum: self.interpolate_spectrum.or(defaults.interpolate_spectrum),
+            analysis_rate_hz: self.analysis_rate_hz.or(defaults.analysis_rate_hz),
+            fit_mode: self.fit_mode.or(defaults.fit_mode),
+            screensaver_idle_secs: self.screensaver_idle_secs.or(defaults.screensaver_idle_secs),
+            max_interpolation_ms: self.max_interpolation_ms.or(defaults.max_interpolation_ms),
+            marquee_mode: self.marquee_mode.or(defaults.marquee_mode),
+            capture_sample_rate: self.capture_sample_rate.or(defaults.capture_sample_rate),
+            audio_device: self.audio_device.or(defaults.audio_device),
+            input_devices: self.input_devices.or(defaults.input_devices),
+            buffer_seconds: self.buffer_seconds.or(defaults.buffer_seconds),
+            compact_icons: self.compact_icons.or(defaults.compact_icons),
+            presets: self.presets.or(defaults.presets),
+            dim_on_pause: self.dim_on_pause.or(defaults.dim_on_pause),
+            pause_dim: self.pause_dim.or(defaults.pause_dim),
+            normalization_exclude_below: self.normalization_exclude_below.or(defaults.normalization_exclude_below),
+            normalization_exclude_above: self.normalization_exclude_above.or(defaults.normalization_exclude_above),
+            auto_gain: self.auto_gain.or(defaults.auto_gain),
+            theme: self.theme.or(defaults.theme),
+            beat_flash: self.beat_flash.or(defaults.beat_flash),
+            onset_sensitivity: self.onset_sensitivity.or(defaults.onset_sensitivity),
+            flash_intensity: self.flash_intensity.or(defaults.flash_intensity),
+            image_loaders: self.image_loaders.or(defaults.image_loaders),
+            max_retries: self.max_retries.or(defaults.max_retries),
+            idle_grace_ms: self.idle_grace_ms.or(defaults.idle_grace_ms),
+            give_up_message: self.give_up_message.or(defaults.give_up_message),
+            auto_preset: self.auto_preset.or(defaults.auto_preset),
+            show_tempo: self.show_tempo.or(defaults.show_tempo),
+            metronome: self.metronome.or(defaults.metronome),
+            spectrum_style: self.spectrum_style.or(defaults.spectrum_style),
+            point_radius: self.point_radius.or(defaults.point_radius),
+            bar_count: self.bar_count.or(defaults.bar_count),
+            progress_offset_ms: self.progress_offset_ms.or(defaults.progress_offset_ms),
+            focus_mode: self.focus_mode.or(defaults.focus_mode),
+            show_header: self.show_header.or(defaults.show_header),
+            ambient_glow: self.ambient_glow.or(defaults.ambient_glow),
+            show_attribution: self.show_attribution.or(defaults.show_attribution),
+            artist_separator: self.artist_separator.or(defaults.artist_separator),
+            primary_artist_only: self.primary_artist_only.or(defaults.primary_artist_only),
+            freq_axis: self.freq_axis.or(defaults.freq_axis),
+            spectrum_tilt_db_per_octave: self.spectrum_tilt_db_per_octave.or(defaults.spectrum_tilt_db_per_octave),
+        }
+    }
+
+    /// Reloads the config file in place. Returns the names of the changed
+    /// settings that only take effect after a restart.
+    pub fn reload(&mut self) -> Result<Vec<&'static str>, confy::ConfyError> {
+        let config = Config::load()?;
+
+        let mut needs_restart = Vec::new();
+        if config.client_id != self.client_id || config.scopes != self.scopes {
+            needs_restart.push("client_id/scopes");
+        }
+        if config.redirect_uri_port != self.redirect_uri_port
+            || config.redirect_bind_address != self.redirect_bind_address
+            || config.callback_page != self.callback_page
+            || config.callback_timeout_secs != self.callback_timeout_secs
+        {
+            needs_restart.push("redirect_uri_port/redirect_bind_address/callback_page/callback_timeout_secs");
+        }
+        if config.decorated != self.decorated
+            || config.appearance != self.appearance
+            || config.window_opacity != self.window_opacity
+            || config.msaa_samples != self.msaa_samples
+            || config.vsync != self.vsync
+        {
+            needs_restart.push("decorated/appearance/window_opacity/msaa_samples/vsync");
+        }
+        if config.capture_sample_rate != self.capture_sample_rate
+            || config.audio_device != self.audio_device
+            || config.input_devices != self.input_devices
+            || config.buffer_seconds != self.buffer_seconds
+        {
+            needs_restart.push("capture_sample_rate/audio_device/input_devices/buffer_seconds");
+        }
+        if config.cover_cache_max_mb != self.cover_cache_max_mb || config.image_loaders != self.image_loaders {
+            needs_restart.push("cover_cache_max_mb/image_loaders");
+        }
+
+        *self = config;
+        Ok(needs_restart)
+    }
+
+    /// Names of the configured presets, in a stable order to cycle through.
+    pub fn preset_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.presets.iter().flat_map(|presets| presets.keys().cloned()).collect();
+        names.sort();
+        names
+    }
+
+    /// This config with the settings of preset `name` applied on top.
+    pub fn with_preset(&self, name: &str) -> Config {
+        let mut config = self.clone();
+        let Some(preset) = self.presets.as_ref().and_then(|presets| presets.get(name)) else {
+            return config;
+        };
+
+        config.band_spacing = preset.band_spacing.or(config.band_spacing);
+        config.reference_pitch = preset.reference_pitch.or(config.reference_pitch);
+        config.spectrum_brightness = preset.spectrum_brightness.or(config.spectrum_brightness);
+        config.spectrum_contrast = preset.spectrum_contrast.or(config.spectrum_contrast);
+        config.silence_threshold = preset.silence_threshold.or(config.silence_threshold);
+        config.gravity = preset.gravity.or(config.gravity);
+        config.smoothing = preset.smoothing.map(clamp_smoothing).or(config.smoothing);
+        config.gain = preset.gain.map(|gain| gain.max(0.0)).or(config.gain);
+        config.invert_spectrum = preset.invert_spectrum.or(config.invert_spectrum);
+        config.interpolate_spectrum = preset.interpolate_spectrum.or(config.interpolate_spectrum);
+        config.analysis_rate_hz = preset.analysis_rate_hz.map(|rate| rate.max(1.0)).or(config.analysis_rate_hz);
+        config
+    }
+
     pub fn store(self) -> Result<(), confy::ConfyError> {
         confy::store(APP_NAME, CONFIG_NAME, self)
     }
+
+    pub fn store_to(self, path: impl AsRef<Path>) -> Result<(), confy::ConfyError> {
+        confy::store_path(path, self)
+    }
+}
+
+fn env_override<T: FromStr>(env_var: &str) -> Option<T> {
+    let value = env::var(env_var).ok()?;
+    match value.parse() {
+        Ok(value) => Some(value),
+        Err(_) => {
+            eprintln!("Ignoring invalid value for {env_var}: {value}");
+            None
+        }
+    }
+}
+
+fn clamp_smoothing(smoothing: f64) -> f64 {
+    smoothing.clamp(0.0, MAX_SMOOTHING)
+}
+
+/// Resolves a setting that can also come from the environment. The
+/// environment variable wins over the config file and the built-in default.
+fn resolve<T: FromStr>(env_var: &str, config_value: Option<T>) -> Option<T> {
+    env_override(env_var).or(config_value)
+}
+
+/// `None` when neither the platform nor HOME tell where it is.
+pub fn app_config_dir() -> Option<PathBuf> {
+    // Linux keeps ~/.config/visify so existing token caches are still found.
+    if cfg!(target_os = "linux") {
+        home_config_dir().or_else(platform_config_dir)
+    } else {
+        platform_config_dir().or_else(home_config_dir)
+    }
+}
+
+/// Where confy keeps the config file on this platform.
+fn platform_config_dir() -> Option<PathBuf> {
+    ProjectDirs::from("rs", "", APP_NAME).map(|dirs| dirs.config_dir().to_path_buf())
 }
 
-pub fn app_config_dir() -> PathBuf {
-    let path = Path::new(env!("HOME"));
-    let home_config_dir = path.join(CONFIG_DIR);
-    home_config_dir.join(APP_NAME)
+/// `~/.config/visify`, with HOME looked up at runtime rather than build time.
+fn home_config_dir() -> Option<PathBuf> {
+    env::var_os("HOME").map(|home| Path::new(&home).join(CONFIG_DIR).join(APP_NAME))
 }
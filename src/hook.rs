@@ -0,0 +1,37 @@
+use std::process::Stdio;
+
+use tokio::{io::AsyncWriteExt, process::Command};
+
+/// Runs `command` through the shell on its own task so a slow or failing
+/// command never holds up the caller. Failures are only logged.
+pub fn run(command: &str, envs: Vec<(&'static str, String)>, stdin: Option<String>) {
+    let (shell, flag) = if cfg!(windows) { ("cmd", "/C") } else { ("sh", "-c") };
+    let mut child = match Command::new(shell)
+        .arg(flag)
+        .arg(command)
+        .envs(envs)
+        .stdin(if stdin.is_some() { Stdio::piped() } else { Stdio::null() })
+        .spawn()
+    {
+        Ok(child) => child,
+        Err(error) => {
+            eprintln!("Failed to run `{command}`: {error}");
+            return;
+        }
+    };
+
+    let command = command.to_string();
+    tokio::spawn(async move {
+        if let (Some(input), Some(mut child_stdin)) = (stdin, child.stdin.take()) {
+            if let Err(error) = child_stdin.write_all(input.as_bytes()).await {
+                eprintln!("Failed to write to `{command}`: {error}");
+            }
+        }
+
+        match child.wait().await {
+            Ok(status) if !status.success() => eprintln!("`{command}` exited with {status}"),
+            Ok(_) => (),
+            Err(error) => eprintln!("Failed to wait for `{command}`: {error}"),
+        }
+    });
+}
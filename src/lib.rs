@@ -1,130 +1,1135 @@
 pub mod auth;
+pub mod cache;
 pub mod config;
+pub mod hook;
+pub mod lyrics;
 pub mod spectrum;
 pub mod state;
-use std::{sync::Arc, thread, result, time};
+use std::{sync::Arc, thread, result, time::{self, Instant}, io::{self, Write}, path::PathBuf};
 use chrono;
 
 use eframe::{egui::{self, Vec2, FontDefinitions}, run_native, CreationContext, NativeOptions, App, Frame, emath::Numeric, epaint::{Color32, FontFamily, FontId}, Storage};
-use rspotify::{AuthCodePkceSpotify, prelude::OAuthClient, model::{AdditionalType, PlayableItem, RepeatState}, ClientError};
-use spectrum::Bode;
-use tokio::sync::mpsc::{channel, Sender, Receiver};
-use state::{State, StateResult, StateError, Client};
+use rspotify::{AuthCodePkceSpotify, model::{AdditionalType, PlayableItem, RepeatState}, ClientError};
+use spectrum::{Bode, BodeError, Visualization, FFT_SIZE};
+use lyrics::Lyrics;
+use tokio::sync::{watch::{channel, Receiver, Sender}, mpsc::UnboundedSender, Notify};
+use state::{State, StateResult, track_changed, StateError, StateSnapshot, Client, Command, ConnectionStatus, Quality, COVER_CACHE_DIR};
+use cache::CoverCacheLoader;
+use config::{Config, Theme, Appearance, ProgressStyle, LayoutMode, Orientation, FitMode, MarqueeMode, ImageLoaders, app_config_dir};
+
+const PROGRESS_FILL_COLOR: Color32 = Color32::from_rgb(122, 36, 39);
+const ACTIVE_ICON_COLOR: Color32 = Color32::from_rgb(196, 39, 39);
+const INACTIVE_ICON_COLOR: Color32 = Color32::from_rgb(156, 116, 116);
+const RING_WIDTH: f32 = 4.;
+const MINI_PROGRESS_HEIGHT: f32 = 3.;
+const STATUS_DOT_RADIUS: f32 = 4.;
+const HEADER_HEIGHT: f32 = 24.;
+const MAX_MSAA_SAMPLES: u16 = 16;
+const MIN_WINDOW_SIZE: Vec2 = Vec2::new(360., 240.);
+const MIN_SIDE_PANEL_WIDTH: f32 = 120.;
+const MIN_TEXT_SIZE: f32 = 10.;
+const WATCH_BLOCKS: [char; 9] = [' ', '▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+const WATCH_FALLBACK_WIDTH: usize = 80;
+const WATCH_FRAME: time::Duration = time::Duration::from_millis(50);
+const PROFILE_ITERATIONS: usize = 1000;
+const PROFILE_SAMPLING_RATE: f32 = 44100.;
+const RING_SEGMENTS: usize = 128;
+const SCREENSAVER_FADE_SECS: f32 = 1.5;
+const SCREENSAVER_ZOOM: f32 = 0.1;
+const SCREENSAVER_DRIFT_SPEED: f64 = 0.05;
+const PAUSE_DIM_FADE_SECS: f32 = 0.5;
+const BEAT_FLASH_SECS: f32 = 0.15;
+const CLEAR_STATE_AFTER_ERRORS: u32 = 3;
+const FOCUS_SPECTRUM_HEIGHT: f32 = 0.2;
+const FOCUS_SPECTRUM_DIM: f32 = 0.35;
+const PRESET_LABEL_SECS: f32 = 2.;
+const COMMAND_ERROR_SECS: f32 = 4.;
+const PREMIUM_REQUIRED_MESSAGE: &str = "Playback control requires Premium";
+const VOLUME_PER_SCROLL_POINT: f32 = 0.1;
+const VOLUME_SCROLL_SETTLE: time::Duration = time::Duration::from_millis(250);
+const VISUALIZATION_KEY: &str = "visualization";
+const ICON_GLYPHS: &str = "";
+const MARQUEE_SPEED: f32 = 40.;
+const MARQUEE_GAP: f32 = 40.;
+const MARQUEE_PAUSE_SECS: f32 = 2.;
+const AMBIENT_GLOW_STEPS: usize = 8;
+const AMBIENT_GLOW_SPREAD: f32 = 40.;
+const AMBIENT_GLOW_ALPHA: f32 = 0.12;
+const AMBIENT_GLOW_FADE_SECS: f32 = 0.3;
+const ATTRIBUTION_SIZE: f32 = 24.;
+/// Spotify's icon as downloaded from its design guidelines, unmodified. It's
+/// Spotify's trademark and not covered by this project's licence, so it isn't
+/// shipped here and is read from the config directory instead.
+const ATTRIBUTION_LOGO_FILE: &str = "spotify-icon.png";
 
 struct Visualizer {
     state: State,
-    bode: Bode,
-    rx: Receiver<StateResult<State>>
+    /// `None` without a usable audio input, leaving out the spectrum.
+    bode: Option<Bode>,
+    rx: Receiver<StateResult<State>>,
+    status_rx: Receiver<ConnectionStatus>,
+    show_diagnostics: bool,
+    progress_style: ProgressStyle,
+    window_opacity: f32,
+    layout: LayoutMode,
+    orientation: Orientation,
+    /// The progress fill, which the spectrum shares.
+    accent: Color32,
+    icon_active: Color32,
+    icon_inactive: Color32,
+    lyrics_dir: Option<PathBuf>,
+    lyrics: Option<Lyrics>,
+    show_lyrics: bool,
+    fit_mode: FitMode,
+    config: Config,
+    config_tx: Sender<Config>,
+    screensaver_idle: Option<time::Duration>,
+    last_activity: Instant,
+    max_interpolation: chrono::Duration,
+    marquee_mode: MarqueeMode,
+    compact_icons: bool,
+    icon_font: Option<bool>,
+    visualization: Visualization,
+    active_preset: Option<String>,
+    preset_changed: Option<Instant>,
+    pause_dim: Option<f32>,
+    flash_intensity: Option<f32>,
+    last_flash: Option<Instant>,
+    retry: Arc<Notify>,
+    gave_up: bool,
+    give_up_message: String,
+    progress_offset: chrono::Duration,
+    focus: bool,
+    show_header: bool,
+    ambient_glow: bool,
+    metronome: bool,
+    show_attribution: bool,
+    /// `Some(None)` once loading the logo failed, so it's only tried once.
+    attribution_logo: Option<Option<egui::TextureHandle>>,
+    artist_separator: String,
+    primary_artist_only: bool,
+    consecutive_errors: u32,
+    idle_grace: time::Duration,
+    playback_absent_since: Option<Instant>,
+    follow_override: Option<(String, bool)>,
+    commands: UnboundedSender<Command>,
+    command_error_rx: Receiver<Option<String>>,
+    command_error: Option<(String, Instant)>,
+    scrolled_volume: Option<f32>,
+    volume_scrolled_at: Option<Instant>,
+    /// Set from a skip until the client reports back.
+    skipping: bool,
+    /// Marquees start over from here, so each track's names begin readable.
+    track_shown_at: Instant,
 }
 
 impl Visualizer {
-    fn new(rx: Receiver<StateResult<State>>) -> Self {
+    fn new(
+        rx: Receiver<StateResult<State>>,
+        client: &Client,
+        config: &Config,
+        config_tx: Sender<Config>,
+    ) -> Self {
         Self {
             state: State::default(),
-            bode: Bode::new(),
-            rx
+            bode: Bode::new(config)
+                .map_err(|error| eprintln!("Showing no spectrum: {error}"))
+                .ok(),
+            rx,
+            status_rx: client.status(),
+            show_diagnostics: false,
+            progress_style: config.progress_style.unwrap(),
+            window_opacity: config.window_opacity.unwrap(),
+            layout: config.layout.unwrap(),
+            orientation: config.orientation.unwrap(),
+            accent: theme_color(config, |theme| &theme.progress_fill, "progress_fill", PROGRESS_FILL_COLOR),
+            icon_active: theme_color(config, |theme| &theme.accent, "accent", ACTIVE_ICON_COLOR),
+            icon_inactive: theme_color(config, |theme| &theme.accent_inactive, "accent_inactive", INACTIVE_ICON_COLOR),
+            lyrics_dir: config.lyrics_dir.clone(),
+            lyrics: None,
+            show_lyrics: true,
+            fit_mode: config.fit_mode.unwrap(),
+            config: config.clone(),
+            config_tx,
+            screensaver_idle: config.screensaver_idle_secs.map(time::Duration::from_secs),
+            last_activity: Instant::now(),
+            max_interpolation: chrono::Duration::milliseconds(config.max_interpolation_ms.unwrap() as i64),
+            marquee_mode: config.marquee_mode.unwrap(),
+            compact_icons: config.compact_icons.unwrap(),
+            icon_font: None,
+            visualization: Visualization::Spectrum,
+            active_preset: None,
+            preset_changed: None,
+            pause_dim: config.dim_on_pause.unwrap().then(|| config.pause_dim.unwrap()),
+            flash_intensity: config.beat_flash.unwrap().then(|| config.flash_intensity.unwrap()),
+            last_flash: None,
+            retry: client.retry(),
+            gave_up: false,
+            give_up_message: config.give_up_message.clone().unwrap(),
+            progress_offset: chrono::Duration::milliseconds(config.progress_offset_ms.unwrap()),
+            focus: config.focus_mode.unwrap(),
+            show_header: config.show_header.unwrap(),
+            ambient_glow: config.ambient_glow.unwrap(),
+            metronome: config.metronome.unwrap(),
+            show_attribution: config.show_attribution.unwrap(),
+            attribution_logo: None,
+            artist_separator: config.artist_separator.clone().unwrap(),
+            primary_artist_only: config.primary_artist_only.unwrap(),
+            consecutive_errors: 0,
+            idle_grace: time::Duration::from_millis(config.idle_grace_ms.unwrap()),
+            playback_absent_since: None,
+            follow_override: None,
+            commands: client.commands(),
+            command_error_rx: client.command_errors(),
+            command_error: None,
+            scrolled_volume: None,
+            volume_scrolled_at: None,
+            skipping: false,
+            track_shown_at: Instant::now(),
+        }
+    }
+
+    fn reload_config(&mut self) {
+        match self.config.reload() {
+            Ok(needs_restart) => {
+                for setting in needs_restart {
+                    eprintln!("Changing {setting} requires a restart");
+                }
+            }
+            Err(error) => {
+                eprintln!("Could not reload config: {error}");
+                return;
+            }
+        }
+
+        if self.active_preset.as_ref().is_some_and(|preset| !self.config.preset_names().contains(preset)) {
+            self.active_preset = None;
+        }
+        self.apply_spectrum_config();
+
+        let config = &self.config;
+        self.progress_style = config.progress_style.unwrap();
+        self.accent = theme_color(config, |theme| &theme.progress_fill, "progress_fill", PROGRESS_FILL_COLOR);
+        self.icon_active = theme_color(config, |theme| &theme.accent, "accent", ACTIVE_ICON_COLOR);
+        self.icon_inactive = theme_color(config, |theme| &theme.accent_inactive, "accent_inactive", INACTIVE_ICON_COLOR);
+        self.layout = config.layout.unwrap();
+        self.orientation = config.orientation.unwrap();
+        self.fit_mode = config.fit_mode.unwrap();
+        self.screensaver_idle = config.screensaver_idle_secs.map(time::Duration::from_secs);
+        self.max_interpolation = chrono::Duration::milliseconds(config.max_interpolation_ms.unwrap() as i64);
+        self.marquee_mode = config.marquee_mode.unwrap();
+        self.show_header = config.show_header.unwrap();
+        self.idle_grace = time::Duration::from_millis(config.idle_grace_ms.unwrap());
+        self.ambient_glow = config.ambient_glow.unwrap();
+        self.metronome = config.metronome.unwrap();
+        self.show_attribution = config.show_attribution.unwrap();
+        self.compact_icons = config.compact_icons.unwrap();
+        self.pause_dim = config.dim_on_pause.unwrap().then(|| config.pause_dim.unwrap());
+        self.flash_intensity = config.beat_flash.unwrap().then(|| config.flash_intensity.unwrap());
+        self.give_up_message = config.give_up_message.clone().unwrap();
+        self.progress_offset = chrono::Duration::milliseconds(config.progress_offset_ms.unwrap());
+        self.artist_separator = config.artist_separator.clone().unwrap();
+        self.primary_artist_only = config.primary_artist_only.unwrap();
+        if config.lyrics_dir != self.lyrics_dir {
+            self.lyrics_dir = config.lyrics_dir.clone();
+            self.lyrics = self.lyrics_dir
+                .as_ref()
+                .and_then(|lyrics_dir| Lyrics::find(lyrics_dir, &self.state));
+        }
+        self.config_tx.send_replace(config.clone());
+    }
+
+    fn apply_spectrum_config(&mut self) {
+        let Some(bode) = &mut self.bode else {
+            return;
+        };
+        match &self.active_preset {
+            Some(preset) => bode.apply_config(&self.config.with_preset(preset)),
+            None => bode.apply_config(&self.config),
+        }
+    }
+
+    /// The current lyric line centered on the bottom edge of `art`.
+    fn paint_lyric(&self, ui: &mut egui::Ui, art: egui::Rect, size: f32, alpha: f32) {
+        let lyric_line = self.lyrics
+            .as_ref()
+            .filter(|_| self.show_lyrics && alpha > 0.)
+            .and_then(|lyrics| lyrics.line_at(self.progress()));
+        if let Some(line) = lyric_line {
+            let lyric = egui::RichText::new(line)
+                .size(size)
+                .color(Color32::WHITE.gamma_multiply(alpha))
+                .background_color(Color32::from_black_alpha(160).gamma_multiply(alpha));
+            ui.put(
+                egui::Rect::from_center_size(art.center_bottom(), Vec2::new(art.width(), size * 5. / 3.)),
+                egui::Label::new(lyric).wrap(true)
+            );
+        }
+    }
+
+    /// Art large and centered with just the track name above it, and the
+    /// spectrum faded into the background.
+    fn show_focus(&mut self, ctx: &egui::Context, frame_height: f32, ui_alpha: f32) {
+        if let Some(bode) = &self.bode {
+            egui::TopBottomPanel::bottom("spectrum")
+                .show_separator_line(false)
+                .exact_height(frame_height * FOCUS_SPECTRUM_HEIGHT)
+                .show(ctx, |ui| {
+                    let accent = self.accent.gamma_multiply(FOCUS_SPECTRUM_DIM);
+                    match self.visualization {
+                        Visualization::Spectrum => bode.show(ui, accent),
+                        Visualization::Waveform => bode.show_waveform(ui, accent),
+                    };
+                });
+        }
+
+        egui::CentralPanel::default()
+            .show(ctx, |ui| {
+                ui.vertical_centered(|ui| {
+                    let text_color = ui.visuals().text_color().gamma_multiply(ui_alpha);
+                    ui.label(egui::RichText::new(&self.state.track).size(frame_height * 0.04).color(text_color));
+                });
+
+                let response = self.cover_art(ui, 1. - ui_alpha);
+                self.paint_lyric(ui, response.rect, frame_height * 0.04, ui_alpha);
+            });
+    }
+
+    fn apply_mood_preset(&mut self) {
+        let Some(mood) = self.state.mood else {
+            return;
+        };
+        let preset = mood.preset_name().to_string();
+        if self.config.preset_names().contains(&preset) && self.active_preset.as_ref() != Some(&preset) {
+            self.active_preset = Some(preset);
+            self.preset_changed = Some(Instant::now());
+            self.apply_spectrum_config();
+        }
+    }
+
+    /// Moves on to the next preset, going back to the plain config after the
+    /// last one.
+    fn cycle_preset(&mut self) {
+        let names = self.config.preset_names();
+        let next = match &self.active_preset {
+            Some(preset) => names.iter().position(|name| name == preset).map(|i| i + 1),
+            None => Some(0),
+        };
+        self.active_preset = next.and_then(|i| names.get(i).cloned());
+        self.preset_changed = Some(Instant::now());
+        self.apply_spectrum_config();
+    }
+
+    /// Whether the icon glyphs can actually be drawn. The fonts set up in
+    /// `show` only take effect from the first frame, so this is checked then.
+    fn has_icon_font(&mut self, ctx: &egui::Context) -> bool {
+        *self.icon_font.get_or_insert_with(|| {
+            let has_glyphs = ctx.fonts(|fonts| fonts.has_glyphs(&FontId::proportional(12.), ICON_GLYPHS));
+            if !has_glyphs {
+                eprintln!("Icon font is missing glyphs, falling back to text labels");
+            }
+            has_glyphs
+        })
+    }
+
+    /// Loaded on first use. Without it, the attribution falls back to text.
+    fn attribution_logo(&mut self, ctx: &egui::Context) -> Option<egui::TextureId> {
+        self.attribution_logo.get_or_insert_with(|| {
+            let logo = app_config_dir()
+                .ok_or_else(|| "could not determine the config directory".to_string())
+                .and_then(|dir| std::fs::read(dir.join(ATTRIBUTION_LOGO_FILE)).map_err(|error| error.to_string()))
+                .and_then(|bytes| egui_extras::image::load_image_bytes(&bytes));
+            match logo {
+                Ok(logo) => Some(ctx.load_texture("spotify_logo", logo, egui::TextureOptions::LINEAR)),
+                Err(error) => {
+                    eprintln!("Showing a text attribution, could not load {ATTRIBUTION_LOGO_FILE}: {error}");
+                    None
+                }
+            }
+        }).as_ref().map(egui::TextureHandle::id)
+    }
+
+    /// Follow changes made from here only show up in polled state once the
+    /// artist changes, so they are overlaid until then.
+    fn following_artist(&self) -> bool {
+        match &self.follow_override {
+            Some((artist_id, following)) if self.state.artist_id.as_ref() == Some(artist_id) => *following,
+            _ => self.state.following_artist,
+        }
+    }
+
+    /// A scrolled volume shows until the next poll reports it back.
+    fn volume(&self) -> Option<u8> {
+        let volume = self.state.volume?;
+        Some(self.scrolled_volume.map_or(volume, |scrolled| scrolled.round() as u8))
+    }
+
+    /// Only the volume the wheel settles on is sent, not every step on the way.
+    fn scroll_volume(&mut self, ctx: &egui::Context) {
+        if let Some(volume) = self.state.volume {
+            let scroll = ctx.input(|input| input.scroll_delta.y);
+            if scroll != 0. {
+                let current = self.scrolled_volume.unwrap_or(volume as f32);
+                self.scrolled_volume = Some((current + scroll * VOLUME_PER_SCROLL_POINT).clamp(0., 100.));
+                self.volume_scrolled_at = Some(Instant::now());
+            }
+        }
+
+        if self.volume_scrolled_at.is_some_and(|scrolled_at| scrolled_at.elapsed() >= VOLUME_SCROLL_SETTLE) {
+            self.volume_scrolled_at = None;
+            if let Some(volume) = self.scrolled_volume {
+                self.send_command(Command::Volume(volume.round() as u8));
+            }
+        }
+    }
+
+    /// Free accounts get told why up front rather than getting the API error
+    /// back. Returns whether the command went out.
+    fn send_command(&mut self, command: Command) -> bool {
+        if command.controls_playback() && !self.state.quality.can_control_playback() {
+            self.command_error = Some((PREMIUM_REQUIRED_MESSAGE.to_string(), Instant::now()));
+            return false;
+        }
+        // The client only goes away along with the app.
+        self.commands.send(command).is_ok()
+    }
+
+    fn toggle_follow(&mut self) {
+        let Some(artist_id) = self.state.artist_id.clone() else {
+            return;
+        };
+        let follow = !self.following_artist();
+        self.follow_override = Some((artist_id.clone(), follow));
+        self.send_command(Command::FollowArtist(artist_id, follow));
+    }
+
+    fn artists(&self) -> String {
+        if self.primary_artist_only {
+            return self.state.artists.first().cloned().unwrap_or_default();
+        }
+        self.state.artists.join(&self.artist_separator)
+    }
+
+    fn progress(&self) -> chrono::Duration {
+        // A stalled poll would otherwise let the bar race ahead and then snap
+        // back once the next state arrives.
+        let elapsed = chrono::Duration::from_std(self.state.instant_of_last_refresh.elapsed())
+            .unwrap_or(chrono::Duration::zero())
+            .min(self.max_interpolation);
+        // Spotify measured the position a little before it reached us.
+        (self.state.progress + elapsed + self.progress_offset).clamp(chrono::Duration::zero(), self.state.duration)
+    }
+
+    fn progress_fraction(&self) -> f32 {
+        progress_fraction(self.progress(), self.state.duration)
+    }
+
+    /// `drift` in `[0, 1]` slowly pans and zooms the art, shifting it around
+    /// while the screensaver is active.
+    fn cover_art(&self, ui: &mut egui::Ui, drift: f32) -> egui::Response {
+        let available = ui.available_size();
+        if self.state.cover_art_url.is_empty() {
+            // Leaves the space empty instead of showing a broken image, while
+            // the ring and lyrics still have a square to go around.
+            let rect = egui::Rect::from_center_size(ui.available_rect_before_wrap().center(), Vec2::splat(available.min_elem()));
+            return ui.allocate_rect(rect, egui::Sense::hover());
         }
+        let image = egui::Image::new(self.state.cover_art_url.clone());
+        let full_uv = egui::Rect::from_min_max(egui::pos2(0., 0.), egui::pos2(1., 1.));
+        let time = ui.input(|input| input.time);
+
+        let image = match self.fit_mode {
+            FitMode::Contain => image
+                .max_size(available)
+                .maintain_aspect_ratio(true)
+                .uv(drift_uv(full_uv, drift, time)),
+            FitMode::Stretch => image
+                .fit_to_exact_size(available)
+                .maintain_aspect_ratio(false)
+                .uv(drift_uv(full_uv, drift, time)),
+            FitMode::Cover => {
+                let image_size = image.load_for_size(ui.ctx(), available).ok().and_then(|texture| texture.size());
+                match image_size {
+                    Some(image_size) => image
+                        .fit_to_exact_size(available)
+                        .maintain_aspect_ratio(false)
+                        .uv(drift_uv(cover_uv(image_size, available), drift, time)),
+                    None => image,
+                }
+            }
+        };
+
+        ui.centered_and_justified(|ui| ui.add(image)).inner
+    }
+
+    fn show_track_info(&self, ui: &mut egui::Ui, panel_height: f32, text_color: Color32) {
+        ui.add_space(panel_height * 0.1);
+        let track = egui::RichText::new(format!("{}", self.state.track)).size(text_size(panel_height * 0.10)).color(text_color);
+        let marquee_secs = self.track_shown_at.elapsed().as_secs_f32();
+        marquee_label(ui, track, self.marquee_mode, marquee_secs);
+
+        ui.add_space(panel_height * 0.1);
+        let album = egui::RichText::new(format!("{}", self.state.album)).size(text_size(panel_height * 0.05)).color(text_color);
+        marquee_label(ui, album, self.marquee_mode, marquee_secs);
+
+        ui.add_space(panel_height * 0.1);
+        let artists = egui::RichText::new(self.artists()).size(text_size(panel_height * 0.075)).color(text_color);
+        marquee_label(ui, artists, self.marquee_mode, marquee_secs);
+
+        if self.state.quality != Quality::Unknown {
+            ui.add_space(panel_height * 0.05);
+            let quality = egui::RichText::new(self.state.quality.to_string()).size(text_size(panel_height * 0.04)).color(text_color);
+            ui.label(quality);
+        }
+
+        if let Some(tempo) = self.state.tempo {
+            ui.add_space(panel_height * 0.05);
+            let bpm = egui::RichText::new(format!("{tempo:.0} BPM")).size(text_size(panel_height * 0.04)).color(text_color);
+            let response = ui.label(bpm);
+
+            if self.metronome && self.state.is_playing {
+                // Lights up on every beat and fades until the next one.
+                let beats = self.progress().num_milliseconds() as f32 * tempo / 60_000.;
+                let pulse = (1. - beats.fract()).powi(2);
+                let radius = panel_height * 0.015;
+                ui.painter().circle_filled(
+                    response.rect.right_center() + Vec2::new(3. * radius, 0.),
+                    radius,
+                    self.accent.gamma_multiply(pulse * text_color.a() as f32 / 255.),
+                );
+            }
+        }
+    }
+
+    /// Returns whether the follow icon was clicked.
+    fn show_icons(&mut self, ui: &mut egui::Ui, glyph_size: f32, icon_spacing: f32, ui_alpha: f32) -> bool {
+        let glyph_size = text_size(glyph_size);
+        let active_color = self.icon_active.gamma_multiply(ui_alpha);
+        let inactive_color = self.icon_inactive.gamma_multiply(ui_alpha);
+
+        let (liked_glyph, shuffle_glyph, repeat_glyph, repeat_one_glyph, follow_glyph, following_glyph) = if self.has_icon_font(ui.ctx()) {
+            ("", "", "", "", "", "")
+        } else {
+            ("♥", "shuffle", "repeat", "repeat 1", "follow", "following")
+        };
+
+        ui.add_space(icon_spacing);
+        let liked = egui::RichText::new(liked_glyph)
+            .font(FontId::new(glyph_size, FontFamily::Proportional))
+            .color(if self.state.liked {active_color} else {inactive_color});
+        if ui.add(egui::Label::new(liked).sense(egui::Sense::click())).clicked() {
+            if let Some(track_id) = self.state.track_id.clone().filter(|_| !self.state.is_episode) {
+                self.send_command(Command::SaveTrack(track_id, !self.state.liked));
+            }
+        }
+
+        ui.add_space(icon_spacing);
+        let shuffled = egui::RichText::new(shuffle_glyph)
+            .font(FontId::new(glyph_size, FontFamily::Proportional))
+            .color(if self.state.shuffled {active_color} else {inactive_color});
+        if ui.add(egui::Label::new(shuffled).sense(egui::Sense::click())).clicked() {
+            self.send_command(Command::Shuffle(!self.state.shuffled));
+        }
+
+        ui.add_space(icon_spacing);
+        let (repeat_glyph, repeat_color) = match self.state.repeat_state {
+            RepeatState::Off => (repeat_glyph, inactive_color),
+            RepeatState::Context => (repeat_glyph, active_color),
+            RepeatState::Track => (repeat_one_glyph, active_color)
+        };
+
+        let repeat_state = egui::RichText::new(repeat_glyph)
+            .font(FontId::new(glyph_size, FontFamily::Proportional))
+            .color(repeat_color);
+        if ui.add(egui::Label::new(repeat_state).sense(egui::Sense::click())).clicked() {
+            let repeat_state = match self.state.repeat_state {
+                RepeatState::Off => RepeatState::Context,
+                RepeatState::Context => RepeatState::Track,
+                RepeatState::Track => RepeatState::Off,
+            };
+            self.send_command(Command::Repeat(repeat_state));
+        }
+        if self.state.repeat_count > 0 {
+            let repeat_count = egui::RichText::new(format!("×{}", self.state.repeat_count))
+                .size(text_size(glyph_size * 0.4))
+                .color(inactive_color);
+            ui.label(repeat_count);
+        }
+
+        if let Some(volume) = self.volume() {
+            ui.add_space(icon_spacing);
+            let volume = egui::RichText::new(format!("{volume}%"))
+                .size(text_size(glyph_size * 0.4))
+                .color(inactive_color);
+            ui.label(volume);
+        }
+
+        ui.add_space(icon_spacing);
+        let following = self.following_artist();
+        let follow = egui::RichText::new(if following {following_glyph} else {follow_glyph})
+            .font(FontId::new(glyph_size, FontFamily::Proportional))
+            .color(if following {active_color} else {inactive_color});
+        ui.add(egui::Label::new(follow).sense(egui::Sense::click())).clicked()
+    }
+}
+
+/// Text scales with the window, but stays readable in small ones.
+fn text_size(size: f32) -> f32 {
+    size.max(MIN_TEXT_SIZE)
+}
+
+/// Texture coordinates cropping the longer side of `image_size` so it fills
+/// `area` without being distorted.
+fn cover_uv(image_size: Vec2, area: Vec2) -> egui::Rect {
+    let image_aspect = image_size.x / image_size.y;
+    let area_aspect = area.x / area.y;
+
+    let visible = if image_aspect > area_aspect {
+        Vec2::new(area_aspect / image_aspect, 1.)
+    } else {
+        Vec2::new(1., image_aspect / area_aspect)
+    };
+
+    egui::Rect::from_center_size(egui::pos2(0.5, 0.5), visible)
+}
+
+/// A single line label that scrolls horizontally when it doesn't fit, either
+/// all the time or only while hovered. Otherwise it is cut off with an ellipsis.
+/// Every lap starts with a pause at the beginning of the text, counted from
+/// `secs` ago.
+fn marquee_label(ui: &mut egui::Ui, text: egui::RichText, mode: MarqueeMode, secs: f32) {
+    if let MarqueeMode::Off = mode {
+        ui.label(text);
+        return;
+    }
+
+    let width = ui.available_width();
+    let galley = egui::WidgetText::from(text.clone()).into_galley(ui, Some(false), width, egui::TextStyle::Body);
+    if galley.size().x <= width {
+        ui.label(text);
+        return;
+    }
+
+    let (rect, response) = ui.allocate_exact_size(Vec2::new(width, galley.size().y), egui::Sense::hover());
+    let scrolling = match mode {
+        MarqueeMode::Always => true,
+        MarqueeMode::OnHover => response.hovered(),
+        MarqueeMode::Off => false,
+    };
+
+    if scrolling {
+        let period = galley.size().x + MARQUEE_GAP;
+        let lap_secs = MARQUEE_PAUSE_SECS + period / MARQUEE_SPEED;
+        let offset = ((secs % lap_secs) - MARQUEE_PAUSE_SECS).max(0.) * MARQUEE_SPEED;
+        let painter = ui.painter_at(rect);
+        for x in [rect.left() - offset, rect.left() - offset + period] {
+            painter.galley(egui::pos2(x, rect.top()), galley.galley.clone());
+        }
+    } else {
+        ui.child_ui(rect, *ui.layout()).add(egui::Label::new(text).truncate(true));
+    }
+}
+
+/// Zooms into `uv` by up to `SCREENSAVER_ZOOM` and moves the visible part
+/// along a slow lissajous path, so no pixel stays lit the same way for long.
+fn drift_uv(uv: egui::Rect, drift: f32, time: f64) -> egui::Rect {
+    if drift <= 0. {
+        return uv;
+    }
+
+    let visible = uv.size() * (1. - SCREENSAVER_ZOOM * drift);
+    let slack = (uv.size() - visible) / 2.;
+    let phase = time * SCREENSAVER_DRIFT_SPEED;
+    let offset = Vec2::new(phase.sin() as f32, (phase * 0.7).cos() as f32) * slack;
+
+    egui::Rect::from_center_size(uv.center() + offset, visible)
+}
+
+/// Translucent layers spreading out from `rect` further the louder `level`
+/// is, adding up to a soft glow.
+fn ambient_glow(rect: egui::Rect, level: f32, color: Color32) -> egui::Shape {
+    let layers = (1..=AMBIENT_GLOW_STEPS)
+        .rev()
+        .map(|step| {
+            let spread = AMBIENT_GLOW_SPREAD * level * step as f32 / AMBIENT_GLOW_STEPS as f32;
+            egui::Shape::rect_filled(rect.expand(spread), spread, color.gamma_multiply(AMBIENT_GLOW_ALPHA * level))
+        })
+        .collect();
+    egui::Shape::Vec(layers)
+}
+
+/// Parses `#rrggbb`, with or without the `#`.
+pub fn parse_hex_color(hex: &str) -> Option<Color32> {
+    let hex = hex.strip_prefix('#').unwrap_or(hex);
+    if hex.len() != 6 || !hex.is_ascii() {
+        return None;
     }
+
+    let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+    Some(Color32::from_rgb(channel(0)?, channel(2)?, channel(4)?))
+}
+
+fn theme_color(config: &Config, field: impl Fn(&Theme) -> &Option<String>, name: &str, default: Color32) -> Color32 {
+    let Some(hex) = config.theme.as_ref().and_then(|theme| field(theme).as_ref()) else {
+        return default;
+    };
+    parse_hex_color(hex).unwrap_or_else(|| {
+        eprintln!("theme.{name} should be a hex color like #c42727, not {hex}, keeping the default");
+        default
+    })
+}
+
+/// Fraction of `duration` covered by `progress`, kept within `[0, 1]` even for
+/// zero-length tracks.
+pub fn progress_fraction(progress: chrono::Duration, duration: chrono::Duration) -> f32 {
+    let duration_ms = duration.num_milliseconds();
+    if duration_ms <= 0 {
+        return 0.;
+    }
+
+    (progress.num_milliseconds() as f32 / duration_ms as f32).clamp(0., 1.)
+}
+
+/// Where a click `fraction` of the way along the progress bar seeks to, or
+/// `None` when there is nothing to seek in.
+pub fn seek_position(fraction: f32, duration: chrono::Duration) -> Option<chrono::Duration> {
+    let duration_ms = duration.num_milliseconds();
+    if duration_ms <= 0 {
+        return None;
+    }
+
+    Some(chrono::Duration::milliseconds((fraction.clamp(0., 1.) * duration_ms as f32) as i64))
+}
+
+fn paint_progress_ring(ui: &egui::Ui, rect: egui::Rect, fraction: f32, color: Color32) {
+    let center = rect.center();
+    let radius = rect.width().max(rect.height()) / 2. + RING_WIDTH * 2.;
+    let point_at = |t: f32| {
+        // Start at twelve o'clock and fill clockwise.
+        let angle = t * std::f32::consts::TAU - std::f32::consts::FRAC_PI_2;
+        center + radius * Vec2::angled(angle)
+    };
+
+    let track: Vec<_> = (0..=RING_SEGMENTS).map(|i| point_at(i as f32 / RING_SEGMENTS as f32)).collect();
+    ui.painter().add(egui::Shape::line(track, egui::Stroke::new(RING_WIDTH, ui.visuals().faint_bg_color)));
+
+    let filled_segments = (fraction.clamp(0., 1.) * RING_SEGMENTS as f32).ceil() as usize;
+    let filled: Vec<_> = (0..=filled_segments)
+        .map(|i| point_at((i as f32 / RING_SEGMENTS as f32).min(fraction)))
+        .collect();
+    ui.painter().add(egui::Shape::line(filled, egui::Stroke::new(RING_WIDTH, color)));
 }
 
 impl App for Visualizer {
+    fn save(&mut self, storage: &mut dyn Storage) {
+        eframe::set_value(storage, VISUALIZATION_KEY, &self.visualization);
+    }
+
+    fn clear_color(&self, visuals: &egui::Visuals) -> [f32; 4] {
+        // Panels carry the background alpha themselves, so nothing may show
+        // through from behind them on a transparent window.
+        if self.window_opacity < 1. {
+            [0.; 4]
+        } else if visuals.dark_mode {
+            Color32::from_rgba_unmultiplied(12, 12, 12, 180).to_normalized_gamma_f32()
+        } else {
+            Color32::from_rgba_unmultiplied(248, 248, 248, 180).to_normalized_gamma_f32()
+        }
+    }
+
     fn update(&mut self, ctx: &egui::Context, frame: &mut Frame) {
-        let try_state = self.rx.try_recv();
-        if let Ok(Ok(state)) = try_state {
-            self.state = state;
-        } else if let Ok(Err(error)) = try_state{
-            eprintln!("{error}");
+        let mut mood_changed = false;
+        if self.rx.has_changed().unwrap_or(false) {
+            self.skipping = false;
+            match &*self.rx.borrow_and_update() {
+                Ok(state) => {
+                    if track_changed(&self.state, state) {
+                        self.track_shown_at = Instant::now();
+                        if let Some(bode) = &self.bode {
+                            bode.reset_smoothing();
+                        }
+                        self.lyrics = self.lyrics_dir
+                            .as_ref()
+                            .and_then(|lyrics_dir| Lyrics::find(lyrics_dir, state));
+                    }
+                    mood_changed = state.mood != self.state.mood;
+                    self.state = state.clone();
+                    self.gave_up = false;
+                    self.consecutive_errors = 0;
+                    self.playback_absent_since = None;
+                    if self.volume_scrolled_at.is_none() {
+                        self.scrolled_volume = None;
+                    }
+                }
+                // Skipping tracks briefly reports nothing playing, so that
+                // only clears the display once it has lasted the grace period.
+                Err(StateError::NoContext) => {
+                    self.playback_absent_since.get_or_insert_with(Instant::now);
+                }
+                Err(error) => {
+                    self.gave_up = matches!(error, StateError::GaveUp(_));
+                    eprintln!("{error}");
+
+                    // A single failed poll keeps showing the last good state.
+                    self.consecutive_errors += 1;
+                    if self.consecutive_errors == CLEAR_STATE_AFTER_ERRORS {
+                        self.state = State::default();
+                        self.lyrics = None;
+                    }
+                }
+            }
+        }
+        if mood_changed {
+            self.apply_mood_preset();
+        }
+        if self.command_error_rx.has_changed().unwrap_or(false) {
+            if let Some(error) = &*self.command_error_rx.borrow_and_update() {
+                eprintln!("{error}");
+                self.command_error = Some((error.clone(), Instant::now()));
+            }
+        }
+        if self.playback_absent_since.is_some_and(|since| since.elapsed() >= self.idle_grace) {
+            self.playback_absent_since = None;
+            self.state = State::default();
+            self.lyrics = None;
+        }
+
+        // The client keeps polling on its own task, so a minimized window
+        // only needs to wake up occasionally instead of redrawing the spectrum.
+        if frame.info().window_info.minimized {
+            ctx.request_repaint_after(time::Duration::from_millis(self.config.refresh_rate_ms.unwrap()));
+            return;
+        }
+
+        if ctx.input(|input| input.key_pressed(egui::Key::F5)) {
+            self.reload_config();
+        }
+
+        if let LayoutMode::MiniProgress = self.layout {
+            let screen = ctx.screen_rect();
+            let filled_width = screen.width() * self.progress_fraction();
+            let line = egui::Rect::from_min_size(
+                egui::pos2(screen.left(), screen.bottom() - MINI_PROGRESS_HEIGHT),
+                Vec2::new(filled_width, MINI_PROGRESS_HEIGHT)
+            );
+            ctx.layer_painter(egui::LayerId::background()).rect_filled(line, 0., self.accent);
+
+            ctx.request_repaint();
+            return;
+        }
+
+        let active = ctx.input(|input| input.pointer.delta() != Vec2::ZERO || input.pointer.any_down() || !input.keys_down.is_empty());
+        if active {
+            self.last_activity = Instant::now();
+        }
+        let idle = self.screensaver_idle.is_some_and(|idle| self.last_activity.elapsed() >= idle);
+        let ui_alpha = ctx.animate_bool_with_time(egui::Id::new("screensaver"), !idle, SCREENSAVER_FADE_SECS);
+        let text_color = ctx.style().visuals.text_color().gamma_multiply(ui_alpha);
+
+        if ctx.input(|input| input.key_pressed(egui::Key::D)) {
+            self.show_diagnostics = !self.show_diagnostics;
+        }
+        if ctx.input(|input| input.key_pressed(egui::Key::A)) {
+            if let Some(bode) = &mut self.bode {
+                bode.capture_reference();
+            }
+        }
+        if ctx.input(|input| input.key_pressed(egui::Key::X)) {
+            if let Some(bode) = &mut self.bode {
+                bode.clear_reference();
+            }
+        }
+        if ctx.input(|input| input.key_pressed(egui::Key::L)) {
+            self.show_lyrics = !self.show_lyrics;
+        }
+        if ctx.input(|input| input.key_pressed(egui::Key::V)) {
+            self.visualization = self.visualization.next();
+        }
+        if ctx.input(|input| input.key_pressed(egui::Key::P)) {
+            self.cycle_preset();
+        }
+        if ctx.input(|input| input.key_pressed(egui::Key::G)) {
+            if let Some(bode) = &mut self.bode {
+                bode.toggle_freq_axis();
+            }
+        }
+        if ctx.input(|input| input.key_pressed(egui::Key::F)) {
+            self.focus = !self.focus;
+        }
+        if ctx.input(|input| input.key_pressed(egui::Key::Space)) {
+            let command = if self.state.is_playing { Command::Pause } else { Command::Resume };
+            self.send_command(command);
+        }
+        for (key, command) in [(egui::Key::ArrowRight, Command::Next), (egui::Key::ArrowLeft, Command::Previous)] {
+            if ctx.input(|input| input.key_pressed(key)) {
+                self.skipping = self.send_command(command);
+            }
+        }
+        self.scroll_volume(ctx);
+        if self.gave_up && ctx.input(|input| input.key_pressed(egui::Key::R)) {
+            self.gave_up = false;
+            self.retry.notify_one();
         }
 
         let frame_width = frame.info().window_info.size.x;
         let frame_height = frame.info().window_info.size.y;
 
-        egui::TopBottomPanel::bottom("spectrum")
-            .show_separator_line(false)
-            .exact_height(frame_height * 0.4)
-            .show(ctx, |ui| {
-                self.bode.show(ui);
-            });
+        let portrait = match self.orientation {
+            Orientation::Auto => frame_height > frame_width,
+            Orientation::Landscape => false,
+            Orientation::Portrait => true,
+        };
+        let (spectrum_height, progress_height) = if portrait { (0.25, 0.05) } else { (0.4, 0.1) };
 
-        egui::TopBottomPanel::bottom("progress_bar")
-            .show_separator_line(false)
-            .exact_height(frame_height * 0.1)
-            .show(ctx, |ui| {
-                let progress = self.state.progress + chrono::Duration::from_std(self.state.instant_of_last_refresh.elapsed()).unwrap_or(chrono::Duration::zero());
-                let progress_bar = egui::ProgressBar::new(
-                    progress.num_milliseconds() as f32 / self.state.duration.num_milliseconds() as f32
-                )
-                    .text(format!("{} / {}", format_duration(progress), format_duration(self.state.duration)))
-                    .fill(Color32::from_rgb(122, 36, 39));
-                ui.add(progress_bar);
-            });
+        let status_color = match *self.status_rx.borrow() {
+            ConnectionStatus::Connected => Color32::from_rgb(60, 170, 80),
+            ConnectionStatus::Degraded => Color32::from_rgb(220, 180, 50),
+            ConnectionStatus::Disconnected => Color32::from_rgb(200, 50, 50),
+        };
 
-        egui::CentralPanel::default()
-            .show(ctx, |ui| {
-                let info_layout = egui::Layout::top_down(eframe::emath::Align::Center);
+        let mut follow_clicked = false;
+        let header_shown = self.show_header && !self.focus;
+        if self.focus {
+            self.show_focus(ctx, frame_height, ui_alpha);
+        } else {
+            if header_shown {
+                egui::TopBottomPanel::top("header")
+                    .show_separator_line(false)
+                    .exact_height(HEADER_HEIGHT)
+                    .show(ctx, |ui| {
+                        ui.horizontal_centered(|ui| {
+                            if let Some(context_image_url) = &self.state.context_image_url {
+                                let thumbnail = egui::Image::new(context_image_url.clone())
+                                    .fit_to_exact_size(Vec2::splat(HEADER_HEIGHT - 4.))
+                                    .tint(Color32::WHITE.gamma_multiply(ui_alpha));
+                                ui.add(thumbnail);
+                            }
+                            let context = self.state.context.as_deref().unwrap_or(&self.state.album);
+                            ui.label(egui::RichText::new(context).color(text_color));
+                            if let Some(device) = &self.state.device {
+                                ui.label(egui::RichText::new(format!("on {device}")).color(text_color));
+                            }
 
-                let panel_height = frame_height * 0.5;
+                            ui.with_layout(egui::Layout::right_to_left(eframe::emath::Align::Center), |ui| {
+                                let (dot, _) = ui.allocate_exact_size(Vec2::splat(2. * STATUS_DOT_RADIUS), egui::Sense::hover());
+                                ui.painter().circle_filled(dot.center(), STATUS_DOT_RADIUS, status_color.gamma_multiply(ui_alpha));
+                            });
+                        });
+                    });
+            }
 
-                egui::SidePanel::left("track_info")
+            if let Some(bode) = &self.bode {
+                egui::TopBottomPanel::bottom("spectrum")
                     .show_separator_line(false)
-                    .exact_width(frame_width / 3.)
+                    .exact_height(frame_height * spectrum_height)
                     .show(ctx, |ui| {
-                    ui.with_layout(info_layout, |ui| {
-                        ui.add_space(panel_height * 0.1);
-                        let track = egui::RichText::new(format!("{}", self.state.track)).size(panel_height * 0.10);
-                        ui.label(track);
-
-                        ui.add_space(panel_height * 0.1);
-                        let album = egui::RichText::new(format!("{}", self.state.album)).size(panel_height * 0.05);
-                        ui.label(album);
-
-                        ui.add_space(panel_height * 0.1);
-                        let artists = egui::RichText::new(format!("{}", self.state.artists.join(", "))).size(panel_height * 0.075);
-                        ui.label(artists);
+                        match self.visualization {
+                            Visualization::Spectrum => bode.show(ui, self.accent),
+                            Visualization::Waveform => bode.show_waveform(ui, self.accent),
+                        };
                     });
-                });
+            }
 
-                let icons_layout = egui::Layout::top_down(eframe::emath::Align::Center);
-                egui::SidePanel::right("icons")
+            if let ProgressStyle::Bar = self.progress_style {
+                egui::TopBottomPanel::bottom("progress_bar")
                     .show_separator_line(false)
-                    .exact_width(frame_width / 3.)
+                    .exact_height(frame_height * progress_height)
                     .show(ctx, |ui| {
-                        ui.with_layout(icons_layout, |ui| {
-                            let active_color = Color32::from_rgb(196, 39, 39);
-                            let inactive_color = Color32::from_rgb(156, 116, 116);
-
-                            ui.add_space(panel_height * 0.1);
-                            let liked = egui::RichText::new("")
-                                .font(FontId::new(panel_height * 0.1, FontFamily::Proportional))
-                                .color(if self.state.liked {active_color} else {inactive_color});
-                            ui.label(liked);
-
-                            ui.add_space(panel_height * 0.1);
-                            let shuffled = egui::RichText::new("")
-                                .font(FontId::new(panel_height * 0.1, FontFamily::Proportional))
-                                .color(if self.state.shuffled {active_color} else {inactive_color});
-                            ui.label(shuffled);
-
-                            ui.add_space(panel_height * 0.1);
-                            let (repeat_glyph, repeat_color) = match self.state.repeat_state {
-                                RepeatState::Off => ("", inactive_color),
-                                RepeatState::Context => ("", active_color),
-                                RepeatState::Track => ("", active_color)
-                            };
-
-                            let repeat_state = egui::RichText::new(repeat_glyph)
-                                .font(FontId::new(panel_height * 0.1, FontFamily::Proportional))
-                                .color(repeat_color);
-                            ui.label(repeat_state);
-                        });
+                        if ui_alpha <= 0. {
+                            return;
+                        }
+                        let progress = self.progress();
+                        let progress_text = format!("{} / {}", format_duration(progress), format_duration(self.state.duration));
+                        let progress_bar = egui::ProgressBar::new(self.progress_fraction())
+                            .text(egui::RichText::new(progress_text).color(text_color))
+                            .fill(self.accent.gamma_multiply(ui_alpha));
+                        let response = ui.add(progress_bar).interact(egui::Sense::click());
+                        if let Some(position) = response.interact_pointer_pos().filter(|_| response.clicked()) {
+                            let fraction = (position.x - response.rect.left()) / response.rect.width();
+                            if let Some(position) = seek_position(fraction, self.state.duration) {
+                                self.send_command(Command::Seek(position));
+                            }
+                        }
+                    });
+            }
+
+            egui::CentralPanel::default()
+                .show(ctx, |ui| {
+                    let info_layout = egui::Layout::top_down(eframe::emath::Align::Center);
+
+                    // Tall windows are narrower than the art is high, so size by width.
+                    let panel_height = if portrait { frame_width * 0.5 } else { frame_height * 0.5 };
+
+                    if portrait {
+                        egui::TopBottomPanel::bottom("icons")
+                            .show_separator_line(false)
+                            .exact_height(panel_height * 0.2)
+                            .show(ctx, |ui| {
+                                ui.with_layout(egui::Layout::left_to_right(eframe::emath::Align::Min), |ui| {
+                                    follow_clicked = self.show_icons(ui, panel_height * 0.1, panel_height * 0.1, ui_alpha);
+                                });
+                            });
+
+                        egui::TopBottomPanel::bottom("track_info")
+                            .show_separator_line(false)
+                            .exact_height(panel_height * 0.7)
+                            .show(ctx, |ui| {
+                                ui.with_layout(info_layout, |ui| self.show_track_info(ui, panel_height, text_color));
+                            });
+                    } else {
+                        egui::SidePanel::left("track_info")
+                            .show_separator_line(false)
+                            .exact_width((frame_width / 3.).max(MIN_SIDE_PANEL_WIDTH))
+                            .show(ctx, |ui| {
+                                ui.with_layout(info_layout, |ui| self.show_track_info(ui, panel_height, text_color));
+                            });
+
+                        let (icons_layout, icons_width, glyph_size, icon_spacing) = if self.compact_icons {
+                            (egui::Layout::left_to_right(eframe::emath::Align::Min), (frame_width / 5.).max(MIN_SIDE_PANEL_WIDTH), panel_height * 0.05, panel_height * 0.05)
+                        } else {
+                            (egui::Layout::top_down(eframe::emath::Align::Center), (frame_width / 3.).max(MIN_SIDE_PANEL_WIDTH), panel_height * 0.1, panel_height * 0.1)
+                        };
+                        egui::SidePanel::right("icons")
+                            .show_separator_line(false)
+                            .exact_width(icons_width)
+                            .show(ctx, |ui| {
+                                ui.with_layout(icons_layout, |ui| {
+                                    follow_clicked = self.show_icons(ui, glyph_size, icon_spacing, ui_alpha);
+                                });
+                            });
+                    }
+
+                    egui::CentralPanel::default()
+                        .show(ctx, |ui| {
+                            // Reserved before the art so the glow ends up behind it.
+                            let glow = ui.painter().add(egui::Shape::Noop);
+                            let response = self.cover_art(ui, 1. - ui_alpha);
+
+                            if self.ambient_glow {
+                                let level = ctx.animate_value_with_time(egui::Id::new("ambient_glow"), self.bode.as_ref().map_or(0., Bode::bass_level), AMBIENT_GLOW_FADE_SECS);
+                                ui.painter().set(glow, ambient_glow(response.rect, level, self.accent.gamma_multiply(ui_alpha)));
+                            }
+
+                            if let ProgressStyle::Ring = self.progress_style {
+                                paint_progress_ring(ui, response.rect, self.progress_fraction(), self.accent.gamma_multiply(ui_alpha));
+                            }
+
+                            self.paint_lyric(ui, response.rect, panel_height * 0.06, ui_alpha);
+                        })
                 });
+        }
+        if follow_clicked {
+            self.toggle_follow();
+        }
 
-                egui::CentralPanel::default()
-                    .show(ctx, |ui| {
-                        let image = egui::Image::new(self.state.cover_art_url.clone());
-                        ui.add(image);
-                    })
-            });
+        if let Some(flash_intensity) = self.flash_intensity {
+            if self.bode.as_ref().is_some_and(Bode::take_onset) {
+                self.last_flash = Some(Instant::now());
+            }
+            let flash = self.last_flash
+                .map(|last_flash| 1. - last_flash.elapsed().as_secs_f32() / BEAT_FLASH_SECS)
+                .unwrap_or(0.)
+                .max(0.);
+            if flash > 0. {
+                ctx.layer_painter(egui::LayerId::new(egui::Order::PanelResizeLine, egui::Id::new("beat_flash")))
+                    .rect_filled(ctx.screen_rect(), 0., Color32::from_white_alpha((flash * flash_intensity * 255.) as u8));
+            }
+        }
+
+        if let Some(pause_dim) = self.pause_dim {
+            let dim = pause_dim * ctx.animate_bool_with_time(egui::Id::new("pause_dim"), !self.state.is_playing, PAUSE_DIM_FADE_SECS);
+            // Above the panels, below overlays like the diagnostics.
+            ctx.layer_painter(egui::LayerId::new(egui::Order::PanelResizeLine, egui::Id::new("pause_dim")))
+                .rect_filled(ctx.screen_rect(), 0., Color32::from_black_alpha((dim * 255.) as u8));
+        }
+
+        // The header has a dot of its own.
+        if !header_shown {
+            let screen = ctx.screen_rect();
+            ctx.layer_painter(egui::LayerId::new(egui::Order::Foreground, egui::Id::new("connection_status")))
+                .circle_filled(screen.right_top() + Vec2::new(-2. * STATUS_DOT_RADIUS, 2. * STATUS_DOT_RADIUS), STATUS_DOT_RADIUS, status_color.gamma_multiply(ui_alpha));
+        }
+
+        if self.gave_up {
+            egui::Area::new("gave_up")
+                .anchor(egui::Align2::CENTER_CENTER, Vec2::ZERO)
+                .show(ctx, |ui| {
+                    egui::Frame::popup(ui.style()).show(ui, |ui| {
+                        ui.label(egui::RichText::new(&self.give_up_message).size(24.).color(Color32::from_rgb(200, 50, 50)));
+                    });
+                });
+        }
+
+        if self.preset_changed.is_some_and(|changed| changed.elapsed().as_secs_f32() < PRESET_LABEL_SECS) {
+            let preset = self.active_preset.as_deref().unwrap_or("default");
+            egui::Area::new("preset")
+                .anchor(egui::Align2::CENTER_TOP, Vec2::new(0., 8.))
+                .show(ctx, |ui| {
+                    egui::Frame::popup(ui.style()).show(ui, |ui| {
+                        ui.label(format!("Preset: {preset}"));
+                    });
+                });
+        }
+
+        if self.skipping {
+            egui::Area::new("skipping")
+                .anchor(egui::Align2::CENTER_TOP, Vec2::new(0., 8.))
+                .show(ctx, |ui| {
+                    egui::Frame::popup(ui.style()).show(ui, |ui| {
+                        ui.label("Skipping…");
+                    });
+                });
+        }
+
+        if let Some((error, _)) = self.command_error.as_ref().filter(|(_, since)| since.elapsed().as_secs_f32() < COMMAND_ERROR_SECS) {
+            egui::Area::new("command_error")
+                .anchor(egui::Align2::CENTER_BOTTOM, Vec2::new(0., -8.))
+                .order(egui::Order::Foreground)
+                .show(ctx, |ui| {
+                    egui::Frame::popup(ui.style()).show(ui, |ui| {
+                        ui.label(egui::RichText::new(error).color(Color32::from_rgb(200, 50, 50)));
+                    });
+                });
+        }
+
+        if self.show_attribution {
+            let logo = self.attribution_logo(ctx);
+            egui::Area::new("attribution")
+                .anchor(egui::Align2::RIGHT_BOTTOM, Vec2::new(-8., -8.))
+                .order(egui::Order::Foreground)
+                .interactable(false)
+                .show(ctx, |ui| match logo {
+                    Some(logo) => {
+                        ui.add(egui::Image::new((logo, Vec2::splat(ATTRIBUTION_SIZE))).tint(Color32::WHITE.gamma_multiply(ui_alpha)));
+                    }
+                    None => {
+                        ui.label(egui::RichText::new("Spotify").size(text_size(ATTRIBUTION_SIZE * 0.6)).color(Color32::WHITE.gamma_multiply(ui_alpha)));
+                    }
+                });
+        }
+
+        if self.show_diagnostics {
+            let diagnostics = self.bode.as_ref().map(Bode::diagnostics);
+            egui::Area::new("diagnostics")
+                .anchor(egui::Align2::LEFT_TOP, Vec2::new(8., 8.))
+                .show(ctx, |ui| {
+                    egui::Frame::popup(ui.style()).show(ui, |ui| {
+                        match &diagnostics {
+                            Some(diagnostics) => {
+                                ui.label(format!("Device: {}", diagnostics.device_name));
+                                ui.label(format!("Sample rate: {} Hz", diagnostics.sampling_rate));
+                                ui.label(format!("Channels: {}", diagnostics.channels));
+                                ui.label(format!("FFT size: {}", diagnostics.fft_size));
+                                ui.label(format!("RMS: {:.4}", diagnostics.rms));
+                            }
+                            None => {
+                                ui.label("Device: none");
+                            }
+                        }
+                        ui.label(format!("Account: {}", self.state.quality));
+                    });
+                });
+        }
 
         ctx.request_repaint();
     }
@@ -137,31 +1142,73 @@ fn format_two_digit_int(number: i64) -> String {
     format!("{}{}", tens.to_string(), ones.to_string())
 }
 
-fn format_duration(duration: chrono::Duration) -> String {
-    let minutes = duration.num_minutes();
+/// `mm:ss`, or `h:mm:ss` from one hour on.
+pub fn format_duration(duration: chrono::Duration) -> String {
+    let hours = duration.num_hours();
+    let minutes = duration.num_minutes() % 60;
     let seconds = duration.num_seconds() % 60;
+    if hours > 0 {
+        return format!("{}:{}:{}", hours, format_two_digit_int(minutes), format_two_digit_int(seconds));
+    }
+
     format!("{}:{}",
     format_two_digit_int(minutes),
     format_two_digit_int(seconds)
 )
 }
 
-pub fn show(client: Arc<AuthCodePkceSpotify>) -> eframe::Result<()> {
-    let (tx, rx) = channel(1);
-    let client = Client::new(client, tx);
-    let visualizer = Visualizer::new(rx);
+pub fn show(client: Arc<AuthCodePkceSpotify>, config: Config) -> eframe::Result<()> {
+    let (tx, rx) = channel(Err(StateError::NoContext));
+    let (config_tx, config_rx) = channel(config.clone());
+    let client = Client::new(client, tx, config_rx);
+    let mut visualizer = Visualizer::new(rx, &client, &config, config_tx);
+    let window_opacity = config.window_opacity.unwrap();
+    let image_loaders = config.image_loaders.unwrap();
+    if image_loaders == ImageLoaders::CacheOnly && config.cover_cache_max_mb.unwrap() == 0 {
+        eprintln!("Cover art can't load with image_loaders set to CacheOnly while the cover cache is disabled");
+    }
 
     client.spawn();
 
     let mut native_options = NativeOptions::default();
     native_options.initial_window_size = Some(Vec2::new(750., 500.));
-    native_options.max_window_size = Some(Vec2::new(750., 500.));
+    native_options.min_window_size = Some(MIN_WINDOW_SIZE);
+    native_options.decorated = config.decorated.unwrap();
+    let appearance = config.appearance.unwrap();
+    native_options.follow_system_theme = appearance == Appearance::Auto;
+    native_options.default_theme = match appearance {
+        Appearance::Light => eframe::Theme::Light,
+        Appearance::Auto | Appearance::Dark => eframe::Theme::Dark,
+    };
+    native_options.transparent = window_opacity < 1.;
+    // eframe keeps the size and position in the same storage as the
+    // visualization, so the initial size only applies to the first run.
+    native_options.persist_window = true;
+    native_options.vsync = config.vsync.unwrap();
+    native_options.multisampling = match config.msaa_samples.unwrap() {
+        samples if samples == 0 || (samples.is_power_of_two() && samples <= MAX_MSAA_SAMPLES) => samples,
+        samples => {
+            eprintln!("msaa_samples must be 0 or a power of two up to {MAX_MSAA_SAMPLES}, not {samples}, turning MSAA off");
+            0
+        }
+    };
 
     run_native(
         "Rofify Visualizer",
         native_options,
-        Box::new(|cc| {
-            egui_extras::install_image_loaders(&cc.egui_ctx);
+        Box::new(move |cc| {
+            match image_loaders {
+                ImageLoaders::All => egui_extras::install_image_loaders(&cc.egui_ctx),
+                ImageLoaders::CacheOnly => {
+                    if let Some(config_dir) = app_config_dir() {
+                        cc.egui_ctx.add_image_loader(Arc::new(CoverCacheLoader::new(config_dir.join(COVER_CACHE_DIR))));
+                    }
+                }
+            }
+
+            if let Some(visualization) = cc.storage.and_then(|storage| eframe::get_value(storage, VISUALIZATION_KEY)) {
+                visualizer.visualization = visualization;
+            }
 
             let mut fonts = FontDefinitions::default();
             fonts.font_data.insert(
@@ -173,7 +1220,108 @@ pub fn show(client: Arc<AuthCodePkceSpotify>) -> eframe::Result<()> {
                 .push("awesome".to_owned());
             cc.egui_ctx.set_fonts(fonts);
 
+            if window_opacity < 1. {
+                let mut visuals = cc.egui_ctx.style().visuals.clone();
+                visuals.panel_fill = visuals.panel_fill.gamma_multiply(window_opacity);
+                visuals.window_fill = visuals.window_fill.gamma_multiply(window_opacity);
+                cc.egui_ctx.set_visuals(visuals);
+            }
+
             Box::new(visualizer)
         })
     )
 }
+
+pub async fn stream_json(client: Arc<AuthCodePkceSpotify>, config: Config) -> io::Result<()> {
+    let (tx, mut rx) = channel(Err(StateError::NoContext));
+    let client = Client::new(client, tx, channel(config).1);
+
+    client.spawn();
+
+    let mut stdout = io::stdout();
+    let mut previous = State::default();
+    while rx.changed().await.is_ok() {
+        match &*rx.borrow_and_update() {
+            Ok(state) => {
+                if !track_changed(&previous, state) {
+                    continue;
+                }
+                previous = state.clone();
+
+                serde_json::to_writer(&mut stdout, &StateSnapshot::from(state))?;
+                writeln!(stdout)?;
+                stdout.flush()?;
+            }
+            Err(error @ StateError::GaveUp(_)) => {
+                eprintln!("{error}");
+                break;
+            }
+            Err(error) => eprintln!("{error}"),
+        }
+    }
+
+    Ok(())
+}
+
+/// Redraws the spectrum as a row of block characters in the terminal, as
+/// wide as the terminal is, until interrupted.
+pub fn watch_spectrum(config: &Config) -> result::Result<(), BodeError> {
+    let bode = Bode::new(config)?;
+    // See https://no-color.org.
+    let color = std::env::var_os("NO_COLOR").is_none() && std::env::var("TERM").is_ok_and(|term| term != "dumb");
+    let accent = theme_color(config, |theme| &theme.progress_fill, "progress_fill", PROGRESS_FILL_COLOR);
+    let mut stdout = io::stdout();
+
+    loop {
+        let width = crossterm::terminal::size().map_or(WATCH_FALLBACK_WIDTH, |(columns, _)| columns as usize);
+        let row: String = bode
+            .latest_bands(width)
+            .iter()
+            .map(|level| WATCH_BLOCKS[(level * (WATCH_BLOCKS.len() - 1) as f32).round() as usize])
+            .collect();
+
+        let written = if color {
+            let [red, green, blue, _] = accent.to_array();
+            write!(stdout, "\r\x1b[38;2;{red};{green};{blue}m{row}\x1b[0m")
+        } else {
+            write!(stdout, "\r{row}")
+        };
+        if written.and_then(|_| stdout.flush()).is_err() {
+            return Ok(());
+        }
+        thread::sleep(WATCH_FRAME);
+    }
+}
+
+pub fn profile_spectrum(config: &Config) {
+    let bode = Bode::synthetic(config, PROFILE_SAMPLING_RATE);
+
+    // A few tones spread across the spectrum, so the smoothing has work to do.
+    let samples: Vec<f32> = (0..FFT_SIZE * 2)
+        .map(|i| {
+            let t = i as f32 / PROFILE_SAMPLING_RATE;
+            [110., 440., 3000.]
+                .iter()
+                .map(|freq| (std::f32::consts::TAU * freq * t).sin() / 3.)
+                .sum()
+        })
+        .collect();
+
+    let mut timings: Vec<time::Duration> = (0..PROFILE_ITERATIONS)
+        .map(|iteration| {
+            let offset = iteration * FFT_SIZE / 16 % FFT_SIZE;
+            let start = Instant::now();
+            bode.analyze(&samples[offset..offset + FFT_SIZE]);
+            start.elapsed()
+        })
+        .collect();
+    timings.sort();
+
+    let mean = timings.iter().sum::<time::Duration>() / PROFILE_ITERATIONS as u32;
+    let p95 = timings[PROFILE_ITERATIONS * 95 / 100];
+    println!("FFT size: {FFT_SIZE}, iterations: {PROFILE_ITERATIONS}");
+    println!("min:  {:?}", timings[0]);
+    println!("mean: {mean:?}");
+    println!("p95:  {p95:?}");
+    println!("max:  {:?}", timings[PROFILE_ITERATIONS - 1]);
+}
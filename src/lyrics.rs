@@ -0,0 +1,74 @@
+use std::{fs, path::Path};
+
+use crate::state::State;
+
+const LYRICS_EXTENSION: &str = "lrc";
+
+/// Time-synced lyrics parsed from an `.lrc` file.
+pub struct Lyrics {
+    lines: Vec<(chrono::Duration, String)>,
+}
+
+fn parse_timestamp(timestamp: &str) -> Option<chrono::Duration> {
+    let (minutes, seconds) = timestamp.split_once(':')?;
+    let minutes: i64 = minutes.parse().ok()?;
+    let seconds: f64 = seconds.parse().ok()?;
+
+    Some(chrono::Duration::milliseconds(minutes * 60_000 + (seconds * 1000.) as i64))
+}
+
+impl Lyrics {
+    pub fn parse(lrc: &str) -> Self {
+        let mut lines = Vec::new();
+
+        for line in lrc.lines() {
+            // A line may carry several timestamps, e.g. `[00:12.00][01:30.50]chorus`.
+            let mut rest = line.trim();
+            let mut timestamps = Vec::new();
+            while let Some(tag) = rest.strip_prefix('[') {
+                let Some((timestamp, after)) = tag.split_once(']') else {
+                    break;
+                };
+                // Metadata tags like `[ar:Artist]` don't parse and are skipped.
+                if let Some(timestamp) = parse_timestamp(timestamp) {
+                    timestamps.push(timestamp);
+                }
+                rest = after;
+            }
+
+            for timestamp in timestamps {
+                lines.push((timestamp, rest.trim().to_string()));
+            }
+        }
+
+        lines.sort_by_key(|(timestamp, _)| *timestamp);
+        Self { lines }
+    }
+
+    /// Looks for `<track id>.lrc` or `<first artist> - <track>.lrc` in `dir`.
+    pub fn find(dir: &Path, state: &State) -> Option<Self> {
+        let mut candidates = Vec::new();
+        if let Some(track_id) = &state.track_id {
+            candidates.push(track_id.clone());
+        }
+        if let Some(artist) = state.artists.first() {
+            candidates.push(format!("{} - {}", artist, state.track));
+        }
+
+        candidates
+            .into_iter()
+            .map(|name| dir.join(format!("{name}.{LYRICS_EXTENSION}")))
+            .find_map(|path| fs::read_to_string(path).ok())
+            .map(|lrc| Self::parse(&lrc))
+            .filter(|lyrics| !lyrics.lines.is_empty())
+    }
+
+    /// The line being sung at `progress`, if any has started yet.
+    pub fn line_at(&self, progress: chrono::Duration) -> Option<&str> {
+        self.lines
+            .iter()
+            .take_while(|(timestamp, _)| *timestamp <= progress)
+            .last()
+            .map(|(_, line)| line.as_str())
+    }
+}
@@ -1,10 +1,63 @@
 use std::{sync::Arc, process::exit};
 
-use visify::{show, auth::auth};
+use visify::{show, stream_json, profile_spectrum, watch_spectrum, auth::{auth, logout}, config::Config};
+
+enum Command {
+    Show,
+    StreamJson,
+    ProfileSpectrum,
+    Watch,
+    Logout,
+}
+
+fn parse_command() -> Command {
+    match std::env::args().nth(1).as_deref() {
+        None => Command::Show,
+        Some("stream-json") => Command::StreamJson,
+        Some("--profile-spectrum") => Command::ProfileSpectrum,
+        Some("watch") => Command::Watch,
+        Some("logout") => Command::Logout,
+        Some(command) => {
+            eprintln!("Unknown command: {command}");
+            exit(1);
+        }
+    }
+}
 
 #[tokio::main]
 async fn main() {
-    let client = Arc::new(match auth().await {
+    let command = parse_command();
+
+    if let Command::Logout = command {
+        if let Err(error) = logout() {
+            eprintln!("Failed to log out: {error}");
+            exit(1);
+        }
+        return;
+    }
+
+    let config = match Config::load() {
+        Ok(config) => config,
+        Err(error) => {
+            eprintln!("Failed to load config: {error}");
+            exit(1);
+        }
+    };
+
+    if let Command::ProfileSpectrum = command {
+        profile_spectrum(&config);
+        return;
+    }
+
+    if let Command::Watch = command {
+        if let Err(error) = watch_spectrum(&config) {
+            eprintln!("Failed to watch the spectrum: {error}");
+            exit(1);
+        }
+        return;
+    }
+
+    let client = Arc::new(match auth(&config).await {
         Ok(client) => client,
         Err(error) => {
             eprintln!("Failed to authenticate with spotify: {error}");
@@ -12,7 +65,15 @@ async fn main() {
         }
     });
 
-    match show(client) {
+    if let Command::StreamJson = command {
+        if let Err(error) = stream_json(client, config).await {
+            eprintln!("Failed to write state: {error}");
+            exit(1);
+        }
+        return;
+    }
+
+    match show(client, config) {
         Ok(_) => (),
         Err(error) => {
             eprintln!("GUI error: {error}");
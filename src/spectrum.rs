@@ -1,5 +1,5 @@
 use std::sync::Mutex;
-use std::{cell::RefCell, sync::Arc};
+use std::{cell::{Cell, RefCell}, sync::Arc, time::{Duration, Instant}};
 use std::cmp::max;
 
 use audio_visualizer::dynamic::live_input::{setup_audio_input_loop, AudioDevAndCfg};
@@ -7,102 +7,845 @@ use audio_visualizer::dynamic::{
     live_input::list_input_devs,
 };
 
-use cpal::Stream;
-use cpal::traits::StreamTrait;
+use cpal::{Device, PlayStreamError, SampleRate, Stream, StreamConfig};
+use cpal::traits::{DeviceTrait, StreamTrait};
 
 use eframe::egui::Ui;
 use eframe::epaint::{Vec2, Color32};
-use egui_plot::{PlotResponse, PlotPoints, Line, Plot, log_grid_spacer, PlotBounds};
+use egui_plot::{PlotResponse, PlotPoints, PlotPoint, Line, Plot, log_grid_spacer, PlotBounds, Text, Points, Bar, BarChart};
 use ringbuffer::{AllocRingBuffer, RingBuffer};
 use spectrum_analyzer::{windows::hann_window, samples_fft_to_spectrum, FrequencyLimit, scaling::divide_by_N, FrequencyValue};
 
+use serde::{Serialize, Deserialize};
+
+use crate::config::{Config, BandSpacing, SpectrumStyle, FreqAxis};
+
+pub const FFT_SIZE: usize = 8192;
+const MIN_FREQUENCY: f64 = 20.0;
+/// Keeps a typo'd `max_frequency` from collapsing the plot.
+const LOWEST_MAX_FREQUENCY: f64 = 100.0;
+const LOG_BANDS_PER_OCTAVE: f64 = 6.0;
+const PLOT_MIN_Y: f64 = -3.0;
+const PLOT_MAX_Y: f64 = 4.0;
+const FILL_Y: f64 = -4.0;
+const REFERENCE_COLOR: Color32 = Color32::from_rgb(120, 170, 200);
+const WAVEFORM_SAMPLES: usize = 2048;
+const BAR_GAP: f64 = 0.2;
+const ONSET_MIN_INTERVAL: Duration = Duration::from_millis(150);
+const FLUX_AVERAGE_WEIGHT: f64 = 0.05;
+const TILT_PIVOT: f64 = 1000.0;
+const BASS_MAX_FREQUENCY: f64 = 150.0;
+const AUTO_GAIN_ATTACK_SECS: f64 = 0.2;
+const AUTO_GAIN_RELEASE_SECS: f64 = 4.0;
+/// Where the tracked peak lands, as a fraction of the plot height.
+const AUTO_GAIN_TARGET_LEVEL: f64 = 0.9;
+/// Keeps near-silence from being blown up into a full-height noise floor.
+const AUTO_GAIN_MAX_BOOST: f64 = 100.0;
+const NOTE_NAMES: [&str; 12] = ["C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B"];
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum Visualization {
+    Spectrum,
+    Waveform,
+}
+
+impl Visualization {
+    pub fn next(self) -> Self {
+        match self {
+            Visualization::Spectrum => Visualization::Waveform,
+            Visualization::Waveform => Visualization::Spectrum,
+        }
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum BodeError {
+    #[error("No audio input devices found")]
+    NoDevices,
+    #[error("No audio input device named {0}")]
+    UnknownDevice(String),
+    #[error("Input device {name} captures at {rate} Hz but the first captures at {expected} Hz, set capture_sample_rate so they match")]
+    MismatchedRates { name: String, rate: u32, expected: u32 },
+    #[error("Failed to start the audio stream: {0}")]
+    Stream(#[from] PlayStreamError),
+}
+
+pub struct Diagnostics {
+    pub device_name: String,
+    pub sampling_rate: f32,
+    pub channels: u16,
+    pub fft_size: usize,
+    pub rms: f32,
+}
+
 pub struct Bode {
-    stream: Stream,
+    streams: Vec<Stream>,
+    device_name: String,
+    channels: u16,
     sampling_rate: f32,
-    latest_audio_data: Arc<Mutex<AllocRingBuffer<f32>>>,
-    smoothed_spectrum: RefCell<Vec<(f64, f64)>>
+    /// One buffer per capture device, summed into a single signal.
+    inputs: Vec<Arc<Mutex<AllocRingBuffer<f32>>>>,
+    smoothed_spectrum: RefCell<Vec<(f64, f64)>>,
+    velocities: RefCell<Vec<f64>>,
+    last_frame: Cell<Instant>,
+    /// Slowly following peak of the smoothed spectrum, for `auto_gain`.
+    tracked_peak: Cell<f64>,
+    reference_spectrum: Option<Vec<(f64, f64)>>,
+    settings: Settings,
+    interpolation: Option<Interpolation>,
+    onsets: Onsets,
+}
+
+/// Spectral flux onset detection. An onset is a frame whose flux rises well
+/// above its running average, so sustained loud passages don't keep firing.
+struct Onsets {
+    previous_magnitudes: RefCell<Vec<f64>>,
+    flux_average: Cell<f64>,
+    last_onset: Cell<Instant>,
+    pending: Cell<bool>,
+}
+
+impl Onsets {
+    fn new() -> Self {
+        Self {
+            previous_magnitudes: RefCell::new(vec![0.0; FFT_SIZE]),
+            flux_average: Cell::new(0.0),
+            last_onset: Cell::new(Instant::now()),
+            pending: Cell::new(false),
+        }
+    }
+
+    fn detect(&self, magnitudes: impl Iterator<Item = f64>, sensitivity: f64) {
+        let flux: f64 = magnitudes
+            .zip(self.previous_magnitudes.borrow_mut().iter_mut())
+            .map(|(magnitude, previous)| (magnitude - std::mem::replace(previous, magnitude)).max(0.0))
+            .sum();
+
+        let average = self.flux_average.get();
+        if flux > average * sensitivity && average > 0.0 && self.last_onset.get().elapsed() >= ONSET_MIN_INTERVAL {
+            self.pending.set(true);
+            self.last_onset.set(Instant::now());
+        }
+        self.flux_average.set(average + (flux - average) * FLUX_AVERAGE_WEIGHT);
+    }
+}
+
+/// The part of the config that can be swapped out while running.
+struct Settings {
+    band_spacing: Option<BandSpacing>,
+    reference_pitch: f64,
+    brightness: f32,
+    contrast: f32,
+    silence_threshold: f32,
+    gravity: Option<f64>,
+    smoothing: f64,
+    gain: f64,
+    max_frequency: f64,
+    invert: bool,
+    onset_sensitivity: f64,
+    style: SpectrumStyle,
+    point_radius: f32,
+    bar_count: usize,
+    freq_axis: FreqAxis,
+    tilt_db_per_octave: f64,
+    auto_gain: bool,
+    normalization_exclude_below: Option<f64>,
+    normalization_exclude_above: Option<f64>,
+}
+
+impl Settings {
+    fn new(config: &Config) -> Self {
+        Self {
+            band_spacing: config.band_spacing,
+            reference_pitch: config.reference_pitch.unwrap(),
+            brightness: config.spectrum_brightness.unwrap(),
+            contrast: config.spectrum_contrast.unwrap(),
+            silence_threshold: config.silence_threshold.unwrap(),
+            gravity: config.gravity,
+            smoothing: config.smoothing.unwrap(),
+            gain: config.gain.unwrap(),
+            max_frequency: config.max_frequency.unwrap() as f64,
+            invert: config.invert_spectrum.unwrap(),
+            onset_sensitivity: config.onset_sensitivity.unwrap(),
+            style: config.spectrum_style.unwrap(),
+            point_radius: config.point_radius.unwrap(),
+            bar_count: config.bar_count.unwrap(),
+            freq_axis: config.freq_axis.unwrap(),
+            tilt_db_per_octave: config.spectrum_tilt_db_per_octave.unwrap(),
+            auto_gain: config.auto_gain.unwrap(),
+            normalization_exclude_below: config.normalization_exclude_below,
+            normalization_exclude_above: config.normalization_exclude_above,
+        }
+    }
+}
+
+/// The two most recent analysis results, blended in between analyses so the
+/// display moves smoothly at any repaint rate.
+struct Interpolation {
+    interval: Duration,
+    last_analysis: Cell<Instant>,
+    previous: RefCell<Vec<(f64, f64)>>,
+    current: RefCell<Vec<(f64, f64)>>,
+}
+
+impl Interpolation {
+    fn new(config: &Config) -> Option<Self> {
+        config.interpolate_spectrum.unwrap().then(|| Self {
+            interval: Duration::from_secs_f64(1.0 / config.analysis_rate_hz.unwrap()),
+            last_analysis: Cell::new(Instant::now()),
+            previous: RefCell::new(vec![(0.0, 0.0); FFT_SIZE]),
+            current: RefCell::new(vec![(0.0, 0.0); FFT_SIZE]),
+        })
+    }
+}
+
+/// The device's default input config at `rate` instead of its default rate,
+/// if the device can capture at that rate.
+fn fixed_rate_config(device: &Device, rate: u32) -> Option<StreamConfig> {
+    let default_config = device.default_input_config().ok()?.config();
+    let supported = device.supported_input_configs().ok()?.any(|range| {
+        range.channels() == default_config.channels
+            && range.min_sample_rate().0 <= rate
+            && rate <= range.max_sample_rate().0
+    });
+
+    if !supported {
+        eprintln!("Capturing at {rate} Hz is not supported, using the device default of {} Hz", default_config.sample_rate.0);
+        return None;
+    }
+
+    Some(StreamConfig {
+        sample_rate: SampleRate(rate),
+        ..default_config
+    })
+}
+
+/// Lets `old` fall towards `new` with accelerating speed, jumping straight up
+/// whenever `new` is higher. Heights are in decades since that's what the
+/// plot shows, so `gravity` is in decades per second squared.
+pub fn fall_under_gravity(new: f64, old: f64, velocity: &mut f64, gravity: f64, dt: f64) -> f64 {
+    *velocity += gravity * dt;
+    let fallen = old.log10() - *velocity * dt;
+
+    if fallen > new.log10() {
+        10.0_f64.powf(fallen)
+    } else {
+        *velocity = 0.0;
+        new
+    }
+}
+
+/// The devices named in `names` in order, or else the single `preferred`
+/// device, falling back to the first one if it isn't around.
+fn input_devices(names: Option<&[String]>, preferred: Option<&str>) -> Result<Vec<(String, Device)>, BodeError> {
+    let mut available = list_input_devs();
+    if available.is_empty() {
+        return Err(BodeError::NoDevices);
+    }
+
+    match names {
+        Some(names) if !names.is_empty() => names
+            .iter()
+            .map(|name| match available.iter().position(|(device_name, _)| device_name == name) {
+                Some(index) => Ok(available.remove(index)),
+                None => Err(BodeError::UnknownDevice(name.clone())),
+            })
+            .collect(),
+        _ => {
+            let index = match preferred {
+                Some(preferred) => available
+                    .iter()
+                    .position(|(device_name, _)| device_name == preferred)
+                    .unwrap_or_else(|| {
+                        eprintln!("No audio input device named {preferred}, using the first one");
+                        0
+                    }),
+                None => 0,
+            };
+            Ok(vec![available.remove(index)])
+        }
+    }
+}
+
+/// Holds `seconds` of audio, but never less than one analysis window.
+fn audio_buffer(sampling_rate: f32, seconds: f32) -> Arc<Mutex<AllocRingBuffer<f32>>> {
+    let mut samples = (seconds * sampling_rate) as usize;
+    if samples < FFT_SIZE {
+        eprintln!("A {seconds} s buffer is shorter than the {FFT_SIZE} sample analysis window, using that instead");
+        samples = FFT_SIZE;
+    }
+    let mut buf = AllocRingBuffer::new(samples.next_power_of_two());
+    buf.fill(0.0);
+    Arc::new(Mutex::new(buf))
+}
+
+fn rms(samples: &[f32]) -> f32 {
+    let sum_of_squares: f32 = samples.iter().map(|sample| sample * sample).sum();
+    (sum_of_squares / samples.len() as f32).sqrt()
+}
+
+/// Contrast stretches each channel around mid-grey, brightness then scales it.
+pub fn adjust_color(color: Color32, brightness: f32, contrast: f32) -> Color32 {
+    let adjust = |channel: u8| {
+        let channel = channel as f32 / 255.0;
+        let adjusted = ((channel - 0.5) * contrast + 0.5) * brightness;
+        (adjusted.clamp(0.0, 1.0) * 255.0).round() as u8
+    };
+
+    let [r, g, b, a] = color.to_srgba_unmultiplied();
+    Color32::from_rgba_unmultiplied(adjust(r), adjust(g), adjust(b), a)
+}
+
+/// Edges of consecutive bands covering `[min_freq, max_freq]`. Musical note
+/// bands are one semitone wide and centered on the equal-tempered pitches
+/// derived from `reference_pitch` (A4).
+pub fn band_edges(spacing: BandSpacing, min_freq: f64, max_freq: f64, reference_pitch: f64) -> Vec<f64> {
+    let (base, steps_per_octave) = match spacing {
+        BandSpacing::Logarithmic => (min_freq, LOG_BANDS_PER_OCTAVE),
+        BandSpacing::MusicalNotes => (reference_pitch * 2.0_f64.powf(-0.5 / 12.0), 12.0),
+    };
+
+    let first_step = (steps_per_octave * (min_freq / base).log2()).floor() as i64;
+    let last_step = (steps_per_octave * (max_freq / base).log2()).ceil() as i64;
+
+    (first_step..=last_step)
+        .map(|step| base * 2.0_f64.powf(step as f64 / steps_per_octave))
+        .collect()
+}
+
+/// Collapses `(frequency, magnitude)` bins into `(center frequency, peak
+/// magnitude)` bands. Bands that no bin falls into are dropped.
+pub fn spectrum_to_bands(spectrum: &[(f64, f64)], edges: &[f64]) -> Vec<(f64, f64)> {
+    edges
+        .windows(2)
+        .filter_map(|edge| {
+            let (low, high) = (edge[0], edge[1]);
+            spectrum
+                .iter()
+                .filter(|(freq, _)| *freq >= low && *freq < high)
+                .map(|(_, freq_val)| *freq_val)
+                .reduce(f64::max)
+                .map(|magnitude| ((low * high).sqrt(), magnitude))
+        })
+        .collect()
+}
+
+/// Loudest magnitude in `spectrum` outside the excluded frequency ranges,
+/// i.e. the level a gain normalization should scale against.
+pub fn normalization_peak(spectrum: &[(f64, f64)], exclude_below: Option<f64>, exclude_above: Option<f64>) -> f64 {
+    spectrum
+        .iter()
+        .filter(|(freq, _)| exclude_below.is_none_or(|below| *freq >= below))
+        .filter(|(freq, _)| exclude_above.is_none_or(|above| *freq <= above))
+        .map(|(_, freq_val)| *freq_val)
+        .fold(0.0, f64::max)
+}
+
+/// Linear gain tilting the spectrum by `db_per_octave` around
+/// [`TILT_PIVOT`], e.g. +3 dB/octave to flatten music's pink-noise slope.
+fn tilt_gain(freq: f64, db_per_octave: f64) -> f64 {
+    if db_per_octave == 0.0 || freq <= 0.0 {
+        return 1.0;
+    }
+    10.0_f64.powf(db_per_octave * (freq / TILT_PIVOT).log2() / 20.0)
+}
+
+/// One point at the center of each of `columns` equal slices of
+/// `[min_x, max_x]`. Slices holding several points keep the highest, so peaks
+/// survive, and empty ones are interpolated from their neighbours.
+pub fn resample(points: &[(f64, f64)], min_x: f64, max_x: f64, columns: usize) -> Vec<(f64, f64)> {
+    if points.len() < 2 || columns == 0 {
+        return points.to_vec();
+    }
+
+    let width = (max_x - min_x) / columns as f64;
+    let mut next = 0;
+    (0..columns)
+        .map(|column| {
+            let low = min_x + column as f64 * width;
+            let x = low + width / 2.0;
+            while next < points.len() && points[next].0 < low {
+                next += 1;
+            }
+
+            let highest = points[next..]
+                .iter()
+                .take_while(|(point_x, _)| *point_x < low + width)
+                .map(|(_, y)| *y)
+                .reduce(f64::max);
+            let y = highest.unwrap_or_else(|| match (next.checked_sub(1).map(|i| points[i]), points.get(next).copied()) {
+                (Some((left_x, left_y)), Some((right_x, right_y))) => left_y + (right_y - left_y) * (x - left_x) / (right_x - left_x),
+                (Some((_, y)), None) | (None, Some((_, y))) => y,
+                (None, None) => FILL_Y,
+            });
+            (x, y)
+        })
+        .collect()
+}
+
+/// Where `magnitude` sits between the bottom and top of the plot.
+fn plot_level(magnitude: f64) -> f32 {
+    ((magnitude.log10() - PLOT_MIN_Y) / (PLOT_MAX_Y - PLOT_MIN_Y)).clamp(0.0, 1.0) as f32
+}
+
+/// Name of the equal-tempered note closest to `freq`, e.g. "A4".
+pub fn note_name(freq: f64, reference_pitch: f64) -> String {
+    let semitones_from_a4 = (12.0 * (freq / reference_pitch).log2()).round() as i64;
+    let semitones_from_c0 = semitones_from_a4 + 9 + 4 * 12;
+
+    format!(
+        "{}{}",
+        NOTE_NAMES[semitones_from_c0.rem_euclid(12) as usize],
+        semitones_from_c0.div_euclid(12)
+    )
 }
 
 impl Bode {
-    pub fn new() -> Self {
-        let audio_device = list_input_devs().remove(0).1;
-        let audio_device_and_config = AudioDevAndCfg::new(Some(audio_device), None);
+    pub fn new(config: &Config) -> Result<Self, BodeError> {
+        let devices = input_devices(config.input_devices.as_deref(), config.audio_device.as_deref())?;
+        let device_name = devices.iter().map(|(name, _)| name.as_str()).collect::<Vec<_>>().join(" + ");
+        println!("Capturing audio from {device_name}");
 
-        let sampling_rate = audio_device_and_config.cfg().sample_rate.0 as f32;
+        let mut streams = Vec::new();
+        let mut inputs = Vec::new();
+        let mut capture_configs = Vec::new();
+        for (name, audio_device) in devices {
+            let stream_config = config.capture_sample_rate.and_then(|rate| fixed_rate_config(&audio_device, rate));
+            let audio_device_and_config = AudioDevAndCfg::new(Some(audio_device), stream_config);
+            capture_configs.push((name, audio_device_and_config.cfg().sample_rate.0, audio_device_and_config.cfg().channels));
 
-        let mut buf = AllocRingBuffer::new((5 * sampling_rate as usize).next_power_of_two());
-        buf.fill(0.0);
-        let latest_audio_data = Arc::new(Mutex::new(buf));
+            let sampling_rate = audio_device_and_config.cfg().sample_rate.0 as f32;
+            let latest_audio_data = audio_buffer(sampling_rate, config.buffer_seconds.unwrap());
+            let stream = setup_audio_input_loop(latest_audio_data.clone(), audio_device_and_config);
+            stream.play()?;
 
-        let smoothed_spectrum = RefCell::new(vec![(0.0, 0.0); 8192]);
+            streams.push(stream);
+            inputs.push(latest_audio_data);
+        }
 
-        let stream = setup_audio_input_loop(latest_audio_data.clone(), audio_device_and_config);
-        stream.play().unwrap();
+        // Samples are summed one to one, which only lines up at the same rate.
+        let (_, sampling_rate, channels) = capture_configs[0];
+        if let Some((name, rate, _)) = capture_configs.iter().find(|(_, rate, _)| *rate != sampling_rate) {
+            return Err(BodeError::MismatchedRates { name: name.clone(), rate: *rate, expected: sampling_rate });
+        }
+
+        Ok(Self::with_input(config, streams, device_name, channels, sampling_rate as f32, inputs))
+    }
 
+    /// A `Bode` without any capture device, fed only through [`Bode::analyze`].
+    pub fn synthetic(config: &Config, sampling_rate: f32) -> Self {
+        Self::with_input(config, Vec::new(), "synthetic".to_string(), 1, sampling_rate, vec![audio_buffer(sampling_rate, config.buffer_seconds.unwrap())])
+    }
+
+    fn with_input(
+        config: &Config,
+        streams: Vec<Stream>,
+        device_name: String,
+        channels: u16,
+        sampling_rate: f32,
+        inputs: Vec<Arc<Mutex<AllocRingBuffer<f32>>>>,
+    ) -> Self {
         Self {
-            stream,
+            streams,
+            device_name,
+            channels,
             sampling_rate,
-            latest_audio_data,
-            smoothed_spectrum
+            inputs,
+            smoothed_spectrum: RefCell::new(vec![(0.0, 0.0); FFT_SIZE]),
+            velocities: RefCell::new(vec![0.0; FFT_SIZE]),
+            last_frame: Cell::new(Instant::now()),
+            tracked_peak: Cell::new(0.0),
+            reference_spectrum: None,
+            settings: Settings::new(config),
+            interpolation: Interpolation::new(config),
+            onsets: Onsets::new(),
         }
     }
 
+    /// Picks up a reloaded config. The capture device is only chosen at
+    /// startup and keeps running as is.
+    pub fn apply_config(&mut self, config: &Config) {
+        let max_frequency = self.max_frequency();
+        self.settings = Settings::new(config);
+        // Bins above a lowered ceiling would otherwise linger on screen.
+        if self.max_frequency() != max_frequency {
+            self.reset_smoothing();
+        }
+        self.interpolation = Interpolation::new(config);
+    }
+
+    /// `max_frequency`, kept within what the sample rate can capture.
+    fn max_frequency(&self) -> f64 {
+        self.settings.max_frequency.clamp(LOWEST_MAX_FREQUENCY, self.sampling_rate as f64 / 2.0)
+    }
+
+    /// The last `count` captured samples of all inputs mixed together, oldest
+    /// first. Only those are copied out of the capture buffers.
+    fn latest_audio(&self, count: usize) -> Vec<f32> {
+        let mut audio = vec![0.0; count];
+        for input in &self.inputs {
+            let input = input.lock().unwrap();
+            let skip = input.len().saturating_sub(count);
+            audio.iter_mut().zip(input.iter().skip(skip)).for_each(|(mixed, sample)| *mixed += sample);
+        }
+        audio
+    }
+
     fn get_spectrum(&self) -> Vec<(f64, f64)> {
-        let audio = self.latest_audio_data.clone().lock().unwrap().to_vec();
-        let relevant_samples = &audio[audio.len() - 8192..];
+        self.analyze(&self.latest_audio(FFT_SIZE))
+    }
 
+    /// Runs the FFT over `relevant_samples` and folds the result into the
+    /// smoothed spectrum, which is returned.
+    pub fn analyze(&self, relevant_samples: &[f32]) -> Vec<(f64, f64)> {
         let hann_window = hann_window(relevant_samples);
-        let latest_spectrum = samples_fft_to_spectrum(
+        let latest_spectrum = match samples_fft_to_spectrum(
             &hann_window,
             self.sampling_rate as u32,
-            FrequencyLimit::Max(10000.0),
+            FrequencyLimit::Max(self.max_frequency() as f32),
             Some(&divide_by_N)
-        ).unwrap();
+        ) {
+            Ok(latest_spectrum) => latest_spectrum,
+            // Hold the last frame rather than taking the whole app down.
+            Err(error) => {
+                eprintln!("Failed to analyze audio: {error:?}");
+                return self.smoothed_spectrum.borrow().clone();
+            }
+        };
+
+        // Below the threshold the FFT only picks up floating-point noise, so
+        // let the smoothed spectrum decay as if nothing came in at all.
+        let silent = rms(relevant_samples) < self.settings.silence_threshold;
+        let dt = self.last_frame.replace(Instant::now()).elapsed().as_secs_f64();
+        self.onsets.detect(latest_spectrum.data().iter().map(|(_, freq_val)| freq_val.val() as f64), self.settings.onset_sensitivity);
 
         latest_spectrum
             .data()
             .iter()
             .zip(self.smoothed_spectrum.borrow_mut().iter_mut())
-            .for_each(|((new_freq, new_freq_val), (old_freq, old_freq_val))| {
+            .zip(self.velocities.borrow_mut().iter_mut())
+            .for_each(|(((new_freq, new_freq_val), (old_freq, old_freq_val)), velocity)| {
                 *old_freq = new_freq.val() as f64;
-                let scaled_old_freq_val = *old_freq_val * 0.84;
-                let max = max(
-                    *new_freq_val * 5000.0_f32.into(),
-                    FrequencyValue::from(scaled_old_freq_val as f32),
-                );
-                *old_freq_val = max.val() as f64;
+                let new_freq_val = if silent { FrequencyValue::from(0.0) } else { *new_freq_val };
+                let tilt = tilt_gain(new_freq.val() as f64, self.settings.tilt_db_per_octave) as f32;
+                let scaled_new_freq_val = new_freq_val * (self.settings.gain as f32 * tilt).into();
+
+                *old_freq_val = match self.settings.gravity {
+                    Some(gravity) => fall_under_gravity(scaled_new_freq_val.val() as f64, *old_freq_val, velocity, gravity, dt),
+                    None => {
+                        let scaled_old_freq_val = *old_freq_val * self.settings.smoothing;
+                        let max = max(
+                            scaled_new_freq_val,
+                            FrequencyValue::from(scaled_old_freq_val as f32),
+                        );
+                        max.val() as f64
+                    }
+                };
             });
 
-        self.smoothed_spectrum.borrow().clone()
+        let spectrum = self.smoothed_spectrum.borrow().clone();
+        if self.settings.auto_gain {
+            self.normalize(spectrum, dt)
+        } else {
+            spectrum
+        }
+    }
+
+    /// Scales `spectrum` so its tracked peak sits near the top of the plot.
+    /// The peak rises quickly but falls slowly, so transients don't pump.
+    fn normalize(&self, mut spectrum: Vec<(f64, f64)>, dt: f64) -> Vec<(f64, f64)> {
+        let peak = normalization_peak(&spectrum, self.settings.normalization_exclude_below, self.settings.normalization_exclude_above);
+        let tracked = self.tracked_peak.get();
+        let time_constant = if peak > tracked { AUTO_GAIN_ATTACK_SECS } else { AUTO_GAIN_RELEASE_SECS };
+        let tracked = tracked + (peak - tracked) * (1.0 - (-dt / time_constant).exp());
+        self.tracked_peak.set(tracked);
+        if tracked <= 0.0 {
+            return spectrum;
+        }
+
+        let target = 10.0_f64.powf(PLOT_MIN_Y + AUTO_GAIN_TARGET_LEVEL * (PLOT_MAX_Y - PLOT_MIN_Y));
+        let boost = (target / tracked).min(AUTO_GAIN_MAX_BOOST);
+        spectrum.iter_mut().for_each(|(_, freq_val)| *freq_val *= boost);
+        spectrum
     }
 
-    pub fn show(&self, ui: &mut Ui) -> PlotResponse<()> {
-        let data = self.get_spectrum();
+    fn interpolated_spectrum(&self, interpolation: &Interpolation) -> Vec<(f64, f64)> {
+        if interpolation.last_analysis.get().elapsed() >= interpolation.interval {
+            let latest = self.get_spectrum();
+            interpolation.previous.replace(interpolation.current.replace(latest));
+            interpolation.last_analysis.set(Instant::now());
+        }
+
+        let t = (interpolation.last_analysis.get().elapsed().as_secs_f64() / interpolation.interval.as_secs_f64()).min(1.0);
+        interpolation.previous
+            .borrow()
+            .iter()
+            .zip(interpolation.current.borrow().iter())
+            .map(|((_, previous_val), (freq, current_val))| (*freq, previous_val + (current_val - previous_val) * t))
+            .collect()
+    }
+
+    pub fn diagnostics(&self) -> Diagnostics {
+        Diagnostics {
+            device_name: self.device_name.clone(),
+            sampling_rate: self.sampling_rate,
+            channels: self.channels,
+            fft_size: FFT_SIZE,
+            rms: rms(&self.latest_audio(FFT_SIZE)),
+        }
+    }
+
+    /// Whether an onset was detected since the last call.
+    pub fn take_onset(&self) -> bool {
+        self.onsets.pending.replace(false)
+    }
+
+    /// Bass level in `[0, 1]` on the plot's scale, for effects that pulse
+    /// with the music.
+    pub fn bass_level(&self) -> f32 {
+        let peak = self.smoothed_spectrum
+            .borrow()
+            .iter()
+            .filter(|(freq, _)| *freq > 0.0 && *freq <= BASS_MAX_FREQUENCY)
+            .map(|(_, freq_val)| *freq_val)
+            .fold(0.0, f64::max);
+        plot_level(peak)
+    }
+
+    /// The latest spectrum in `count` log-spaced bands, each in `[0, 1]` like
+    /// [`Bode::bass_level`]. Needs no UI, so it also drives the terminal.
+    pub fn latest_bands(&self, count: usize) -> Vec<f32> {
+        let spectrum = self.get_spectrum();
+        let ratio = self.max_frequency() / MIN_FREQUENCY;
+        let edges: Vec<f64> = (0..=count)
+            .map(|i| MIN_FREQUENCY * ratio.powf(i as f64 / count as f64))
+            .collect();
+
+        edges
+            .windows(2)
+            .map(|edge| {
+                let (low, high) = (edge[0], edge[1]);
+                // Low bands can be narrower than a bin, those take the closest one.
+                let center = (low * high).sqrt();
+                let peak = spectrum
+                    .iter()
+                    .filter(|(freq, _)| *freq >= low && *freq < high)
+                    .map(|(_, freq_val)| *freq_val)
+                    .reduce(f64::max)
+                    .or_else(|| {
+                        spectrum
+                            .iter()
+                            .min_by(|(a, _), (b, _)| (a - center).abs().total_cmp(&(b - center).abs()))
+                            .map(|(_, freq_val)| *freq_val)
+                    })
+                    .unwrap_or(0.0);
+                plot_level(peak)
+            })
+            .collect()
+    }
+
+    /// Drops the decaying tail of the previous track so it doesn't ghost into
+    /// the next one.
+    pub fn reset_smoothing(&self) {
+        self.smoothed_spectrum.borrow_mut().iter_mut().for_each(|(_, freq_val)| *freq_val = 0.0);
+        self.velocities.borrow_mut().iter_mut().for_each(|velocity| *velocity = 0.0);
+        if let Some(interpolation) = &self.interpolation {
+            for spectrum in [&interpolation.previous, &interpolation.current] {
+                spectrum.borrow_mut().iter_mut().for_each(|(_, freq_val)| *freq_val = 0.0);
+            }
+        }
+    }
+
+    /// Freezes the current spectrum so it is drawn alongside the live one.
+    pub fn capture_reference(&mut self) {
+        self.reference_spectrum = Some(self.smoothed_spectrum.borrow().clone());
+    }
+
+    pub fn clear_reference(&mut self) {
+        self.reference_spectrum = None;
+    }
+
+    fn plot_data(&self, data: Vec<(f64, f64)>) -> Vec<(f64, f64)> {
         let length = data.len();
         let data: Vec<(f64, f64)> = data.into_iter().take((length as f64 / 2.0).floor() as usize).collect();
+        match self.settings.band_spacing {
+            Some(spacing) => {
+                let edges = band_edges(spacing, MIN_FREQUENCY, self.max_frequency(), self.settings.reference_pitch);
+                spectrum_to_bands(&data, &edges)
+            }
+            None => data,
+        }
+    }
+
+    /// Plot height of `y`, mirrored within the plot bounds when the spectrum
+    /// hangs from the top.
+    fn plot_y(&self, y: f64) -> f64 {
+        if self.settings.invert {
+            PLOT_MIN_Y + PLOT_MAX_Y - y
+        } else {
+            y
+        }
+    }
+
+    fn plot_x(&self, freq: f64) -> f64 {
+        match self.settings.freq_axis {
+            FreqAxis::Log => freq.log10(),
+            FreqAxis::Linear => freq,
+        }
+    }
+
+    fn plot_bounds(&self) -> PlotBounds {
+        let (min_x, max_x) = match self.settings.freq_axis {
+            FreqAxis::Log => (1.5, self.max_frequency().log10()),
+            FreqAxis::Linear => (0.0, self.max_frequency()),
+        };
+        PlotBounds::from_min_max([min_x, PLOT_MIN_Y], [max_x, PLOT_MAX_Y])
+    }
+
+    pub fn toggle_freq_axis(&mut self) {
+        self.settings.freq_axis = match self.settings.freq_axis {
+            FreqAxis::Log => FreqAxis::Linear,
+            FreqAxis::Linear => FreqAxis::Log,
+        };
+    }
+
+    fn to_points(&self, data: &[(f64, f64)]) -> PlotPoints {
+        data.iter().map(|(freq, freq_val)| {
+            [self.plot_x(*freq), self.plot_y(freq_val.log10())]
+        }).collect()
+    }
+
+    /// Like [`Bode::to_points`], but resampled to one point per pixel column
+    /// so the line looks equally smooth where bins are sparse and dense.
+    fn to_line_points(&self, data: &[(f64, f64)], columns: usize) -> PlotPoints {
+        let bounds = self.plot_bounds();
+        let points: Vec<(f64, f64)> = data
+            .iter()
+            .map(|(freq, freq_val)| (self.plot_x(*freq), freq_val.log10().max(FILL_Y)))
+            .filter(|(x, _)| x.is_finite())
+            .collect();
+
+        resample(&points, bounds.min()[0], bounds.max()[0], columns)
+            .into_iter()
+            .map(|(x, y)| [x, self.plot_y(y)])
+            .collect()
+    }
 
-        let points: PlotPoints = data.iter().map(|(freq, freq_val)| {
-            [freq.log10().to_owned(), freq_val.log10().to_owned()]
-        }).collect();
+    /// Bars rise from the bottom of the plot, or hang from the top when
+    /// inverted.
+    fn to_bars(&self, data: &[(f64, f64)], color: Color32) -> BarChart {
+        let bounds = self.plot_bounds();
+        let width = (bounds.max()[0] - bounds.min()[0]) / self.settings.bar_count as f64;
+        let base = self.plot_y(PLOT_MIN_Y);
+        let bars = self.to_line_points(data, self.settings.bar_count)
+            .points()
+            .iter()
+            .map(|point| Bar::new(point.x, point.y - base)
+                .base_offset(base)
+                .width(width * (1.0 - BAR_GAP))
+                .fill(color))
+            .collect();
+        BarChart::new(bars).color(color)
+    }
+
+    pub fn show(&self, ui: &mut Ui, accent: Color32) -> PlotResponse<()> {
+        let spectrum = match &self.interpolation {
+            Some(interpolation) => self.interpolated_spectrum(interpolation),
+            None => self.get_spectrum(),
+        };
+        let data = self.plot_data(spectrum);
+
+        let strongest_note = match self.settings.band_spacing {
+            Some(BandSpacing::MusicalNotes) => data
+                .iter()
+                .max_by(|(_, a), (_, b)| a.total_cmp(b))
+                .map(|(freq, freq_val)| (*freq, *freq_val)),
+            _ => None,
+        };
+
+        let columns = ui.available_width() as usize;
         //let points: PlotPoints = data.iter().enumerate().map(|(i, (l, r))| {
         //    let x = i as f64;
         //    [x, (l + r) / 2.0]
         //}).collect();
-        let line = Line::new(points)
-            .fill(-4.0)
-            .width(5.0);
+        let color = adjust_color(accent, self.settings.brightness, self.settings.contrast);
+        let (line, dots, bars) = match self.settings.style {
+            SpectrumStyle::Line => {
+                let line = Line::new(self.to_line_points(&data, columns))
+                    .color(color)
+                    .fill(self.plot_y(FILL_Y) as f32)
+                    .width(5.0);
+                (Some(line), None, None)
+            }
+            SpectrumStyle::Points => (None, Some(Points::new(self.to_points(&data)).color(color).radius(self.settings.point_radius)), None),
+            SpectrumStyle::Bars => (None, None, Some(self.to_bars(&data, color))),
+        };
+        let reference_line = self.reference_spectrum.clone().map(|reference| {
+            Line::new(self.to_line_points(&self.plot_data(reference), columns))
+                .color(REFERENCE_COLOR)
+                .width(2.0)
+        });
         Plot::new("spectrum")
             .show_grid([false; 2])
             .show_axes([false; 2])
             .show_x(false)
             .show_y(false)
-            .view_aspect(15.0 / 4.0)
             .allow_zoom(false)
             .allow_drag(false)
             .allow_scroll(false)
             .show(ui, |plot_ui| {
-                plot_ui.set_plot_bounds(PlotBounds::from_min_max([1.5, -3.0], [4.0, 4.0]));
-                plot_ui.line(line)
+                plot_ui.set_plot_bounds(self.plot_bounds());
+                if let Some(line) = line {
+                    plot_ui.line(line);
+                }
+                if let Some(dots) = dots {
+                    plot_ui.points(dots);
+                }
+                if let Some(bars) = bars {
+                    plot_ui.bar_chart(bars);
+                }
+                if let Some(reference_line) = reference_line {
+                    plot_ui.line(reference_line);
+                }
+
+                if let Some((freq, freq_val)) = strongest_note {
+                    let anchor = if self.settings.invert {
+                        eframe::emath::Align2::CENTER_TOP
+                    } else {
+                        eframe::emath::Align2::CENTER_BOTTOM
+                    };
+                    let label = Text::new(
+                        PlotPoint::new(self.plot_x(freq), self.plot_y(freq_val.log10())),
+                        note_name(freq, self.settings.reference_pitch)
+                    ).anchor(anchor);
+                    plot_ui.text(label);
+                }
+            })
+    }
+
+    pub fn show_waveform(&self, ui: &mut Ui, accent: Color32) -> PlotResponse<()> {
+        let points: PlotPoints = self.latest_audio(WAVEFORM_SAMPLES)
+            .iter()
+            .enumerate()
+            .map(|(i, sample)| {
+                let sample = *sample as f64;
+                [i as f64, if self.settings.invert { -sample } else { sample }]
+            })
+            .collect();
+        let color = adjust_color(accent, self.settings.brightness, self.settings.contrast);
+        let line = Line::new(points).color(color).width(2.0);
+
+        Plot::new("waveform")
+            .show_grid([false; 2])
+            .show_axes([false; 2])
+            .show_x(false)
+            .show_y(false)
+            .allow_zoom(false)
+            .allow_drag(false)
+            .allow_scroll(false)
+            .show(ui, |plot_ui| {
+                plot_ui.set_plot_bounds(PlotBounds::from_min_max([0.0, -1.0], [WAVEFORM_SAMPLES as f64, 1.0]));
+                plot_ui.line(line);
             })
     }
 }
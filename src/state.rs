@@ -1,9 +1,15 @@
-use rspotify::{AuthCodePkceSpotify, prelude::OAuthClient, model::{AdditionalType, PlayableItem, RepeatState}, ClientError};
-use std::{sync::Arc, thread, result, time::{self, Instant}};
+use rspotify::{AuthCodePkceSpotify, prelude::{BaseClient, OAuthClient, Id}, model::{AdditionalType, PlayableItem, RepeatState, FullTrack, FullEpisode, Image, CurrentlyPlayingContext, SubscriptionLevel, AudioFeatures, TrackId, ArtistId, AlbumId, PlaylistId}, ClientError};
+use serde::Serialize;
+use std::{sync::Arc, thread, result, time::{self, Instant}, fmt};
 use chrono;
-use tokio::sync::mpsc::{channel, Sender, Receiver};
+use tokio::sync::{watch::{self, Sender, Receiver}, mpsc::{self, UnboundedSender, UnboundedReceiver}, Notify};
 
-pub const REFRESH_RATE_MS: u64 = 5000;
+use crate::{cache::CoverCache, config::{Config, app_config_dir, MIN_REFRESH_RATE_MS}, hook};
+
+const TRACK_END_MARGIN_MS: u64 = 250;
+const DISCONNECTED_AFTER_ERRORS: u32 = 3;
+pub const COVER_CACHE_DIR: &str = "covers";
+const RESTART_BACKWARD_JUMP_MS: i64 = 2000;
 
 #[derive(thiserror::Error, Debug)]
 pub enum StateError {
@@ -13,11 +19,108 @@ pub enum StateError {
     NoContext,
     #[error("Could not get some of the required state from the client.")]
     MissingState,
+    #[error("Gave up after {0} failed attempts.")]
+    GaveUp(u32),
 }
 
 pub type StateResult<T> = result::Result<T, StateError>;
 
+/// Playback requests from the GUI, carried out by the client task. The next
+/// poll shows their effect, nothing is updated ahead of it.
+#[derive(Clone, Debug)]
+pub enum Command {
+    Pause,
+    Resume,
+    Next,
+    Previous,
+    Seek(chrono::Duration),
+    Volume(u8),
+    Shuffle(bool),
+    Repeat(RepeatState),
+    /// Adds the track to or removes it from the liked songs.
+    SaveTrack(String, bool),
+    /// Follows or unfollows the artist.
+    FollowArtist(String, bool),
+}
+
+impl Command {
+    /// Library changes work on any account, everything else controls playback.
+    pub fn controls_playback(&self) -> bool {
+        !matches!(self, Command::SaveTrack(..) | Command::FollowArtist(..))
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ConnectionStatus {
+    Connected,
+    Degraded,
+    Disconnected,
+}
+
+/// Streaming quality tier, as far as it can be told from the account. Spotify
+/// doesn't report the bitrate of the actual stream.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Quality {
+    Premium,
+    Free,
+    Unknown,
+}
+
+impl Quality {
+    /// Free accounts can't control playback through the API, so controls
+    /// should explain that instead of surfacing the API error.
+    pub fn can_control_playback(&self) -> bool {
+        *self != Quality::Free
+    }
+}
+
+impl fmt::Display for Quality {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Quality::Premium => write!(f, "Premium, up to 320 kbps"),
+            Quality::Free => write!(f, "Free, up to 160 kbps"),
+            Quality::Unknown => write!(f, "unknown"),
+        }
+    }
+}
+
+/// Rough character of a track from its audio features. With `auto_preset`
+/// on, the preset of the same name (e.g. `acoustic`) is picked for it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Mood {
+    Vocal,
+    Acoustic,
+    Energetic,
+    Calm,
+}
+
+impl Mood {
+    fn from_features(features: &AudioFeatures) -> Self {
+        if features.speechiness > 0.33 {
+            Mood::Vocal
+        } else if features.acousticness > 0.6 {
+            Mood::Acoustic
+        } else if features.energy > 0.7 {
+            Mood::Energetic
+        } else {
+            Mood::Calm
+        }
+    }
+
+    pub fn preset_name(&self) -> &'static str {
+        match self {
+            Mood::Vocal => "vocal",
+            Mood::Acoustic => "acoustic",
+            Mood::Energetic => "energetic",
+            Mood::Calm => "calm",
+        }
+    }
+}
+
+#[derive(Clone)]
 pub struct State {
+    pub track_id: Option<String>,
+    pub is_playing: bool,
     pub liked: bool,
     pub shuffled: bool,
     pub repeat_state: RepeatState,
@@ -28,11 +131,49 @@ pub struct State {
     pub album: String,
     pub artists: Vec<String>,
     pub cover_art_url: String,
+    /// A podcast episode, where `album` holds the show and `artists` the
+    /// publisher.
+    pub is_episode: bool,
+    /// Set when the same track started over, e.g. on repeat-one or a seek
+    /// back to the start, which the track id alone can't tell apart.
+    pub restarted: bool,
+    /// How often repeat-one has started the track over this session.
+    pub repeat_count: u32,
+    pub quality: Quality,
+    pub mood: Option<Mood>,
+    /// Beats per minute, only looked up with `show_tempo` on.
+    pub tempo: Option<f32>,
+    /// The primary artist, the one `following_artist` is about.
+    pub artist_id: Option<String>,
+    pub following_artist: bool,
+    pub device: Option<String>,
+    /// Left out by devices that can't have their volume changed.
+    pub volume: Option<u8>,
+    /// The album, playlist or artist playback started from.
+    pub context_uri: Option<String>,
+    pub context: Option<String>,
+    pub context_image_url: Option<String>,
+}
+
+/// Whether `new` is a different play of a track than `old`. Local files have
+/// no id, so those are told apart by name and artists instead. A restart of
+/// the same track, e.g. on repeat-one, counts as a change too.
+pub fn track_changed(old: &State, new: &State) -> bool {
+    if new.restarted {
+        return true;
+    }
+
+    match (&old.track_id, &new.track_id) {
+        (None, None) => old.track != new.track || old.artists != new.artists,
+        (old_id, new_id) => old_id != new_id,
+    }
 }
 
 impl Default for State {
     fn default() -> Self {
         State {
+            track_id: Default::default(),
+            is_playing: Default::default(),
             liked: Default::default(),
             shuffled: Default::default(),
             repeat_state: RepeatState::Off,
@@ -43,74 +184,568 @@ impl Default for State {
             album: Default::default(),
             artists: Default::default(),
             cover_art_url: Default::default(),
+            is_episode: false,
+            restarted: false,
+            repeat_count: 0,
+            quality: Quality::Unknown,
+            mood: None,
+            tempo: None,
+            artist_id: None,
+            following_artist: false,
+            device: None,
+            volume: None,
+            context_uri: None,
+            context: None,
+            context_image_url: None,
         }
     }
 }
 
+#[derive(Serialize, Debug)]
+pub struct StateSnapshot {
+    pub track_id: Option<String>,
+    pub track: String,
+    pub album: String,
+    pub artists: Vec<String>,
+    pub cover_art_url: String,
+    pub is_playing: bool,
+    pub liked: bool,
+    pub shuffled: bool,
+    pub repeat_state: RepeatState,
+    pub progress_ms: i64,
+    pub duration_ms: i64,
+}
+
+impl From<&State> for StateSnapshot {
+    fn from(state: &State) -> Self {
+        Self {
+            track_id: state.track_id.clone(),
+            track: state.track.clone(),
+            album: state.album.clone(),
+            artists: state.artists.clone(),
+            cover_art_url: state.cover_art_url.clone(),
+            is_playing: state.is_playing,
+            liked: state.liked,
+            shuffled: state.shuffled,
+            repeat_state: state.repeat_state,
+            progress_ms: state.progress.num_milliseconds(),
+            duration_ms: state.duration.num_milliseconds(),
+        }
+    }
+}
+
+#[derive(Clone)]
+struct ContextInfo {
+    name: String,
+    is_playlist: bool,
+    image_url: Option<String>,
+}
+
+impl ContextInfo {
+    fn named(name: String) -> Self {
+        Self { name, is_playlist: false, image_url: None }
+    }
+}
+
+/// Polls again shortly after the current track should end, so short tracks
+/// and interludes don't slip by between two regular polls.
+pub fn next_poll_delay(state: &State, refresh_rate_ms: u64) -> u64 {
+    if !state.is_playing {
+        return refresh_rate_ms;
+    }
+
+    let remaining_ms = (state.duration - state.progress).num_milliseconds().max(0) as u64;
+    (remaining_ms + TRACK_END_MARGIN_MS).clamp(MIN_REFRESH_RATE_MS, refresh_rate_ms)
+}
+
 pub struct Client {
     pub client: Arc<AuthCodePkceSpotify>,
-    pub tx: Sender<StateResult<State>>
+    pub tx: Sender<StateResult<State>>,
+    cover_cache: Option<CoverCache>,
+    config_rx: Receiver<Config>,
+    last_notified: Option<State>,
+    last_position: Option<(Option<String>, chrono::Duration)>,
+    repeat_count: u32,
+    quality: Option<Quality>,
+    retry: Arc<Notify>,
+    last_features: Option<(String, Option<AudioFeatures>)>,
+    last_follow: Option<(String, bool)>,
+    last_context: Option<(String, Option<ContextInfo>)>,
+    status_tx: Sender<ConnectionStatus>,
+    consecutive_errors: u32,
+    command_tx: UnboundedSender<Command>,
+    command_rx: UnboundedReceiver<Command>,
+    command_error_tx: Sender<Option<String>>,
 }
 
 impl Client {
-    pub fn new(client: Arc<AuthCodePkceSpotify>, tx: Sender<StateResult<State>>) -> Self {
+    /// The client keeps reading `config_rx` while polling, so reloaded
+    /// settings apply on the next poll.
+    pub fn new(client: Arc<AuthCodePkceSpotify>, tx: Sender<StateResult<State>>, config_rx: Receiver<Config>) -> Self {
+        let cover_cache = match (config_rx.borrow().cover_cache_max_mb.unwrap(), app_config_dir()) {
+            (0, _) => None,
+            (max_mb, Some(config_dir)) => Some(CoverCache::new(config_dir.join(COVER_CACHE_DIR), max_mb * 1024 * 1024)),
+            (_, None) => {
+                eprintln!("Not caching cover art, could not determine the config directory");
+                None
+            }
+        };
+        let (command_tx, command_rx) = mpsc::unbounded_channel();
+
         Self {
             client,
-            tx
+            tx,
+            cover_cache,
+            config_rx,
+            last_notified: None,
+            last_position: None,
+            repeat_count: 0,
+            quality: None,
+            retry: Arc::new(Notify::new()),
+            last_features: None,
+            last_follow: None,
+            last_context: None,
+            status_tx: watch::channel(ConnectionStatus::Connected).0,
+            consecutive_errors: 0,
+            command_tx,
+            command_rx,
+            command_error_tx: watch::channel(None).0,
+        }
+    }
+
+    pub fn status(&self) -> Receiver<ConnectionStatus> {
+        self.status_tx.subscribe()
+    }
+
+    pub fn commands(&self) -> UnboundedSender<Command> {
+        self.command_tx.clone()
+    }
+
+    /// The latest failed command, e.g. for lack of an active device.
+    pub fn command_errors(&self) -> Receiver<Option<String>> {
+        self.command_error_tx.subscribe()
+    }
+
+    async fn run(&self, command: Command) {
+        let (action, result) = match command {
+            Command::Pause => ("pause playback", self.client.pause_playback(None).await),
+            Command::Resume => ("resume playback", self.client.resume_playback(None, None).await),
+            Command::Next => ("skip to the next track", self.client.next_track(None).await),
+            Command::Previous => ("skip to the previous track", self.client.previous_track(None).await),
+            Command::Seek(position) => ("seek", self.client.seek_track(position, None).await),
+            Command::Volume(volume) => ("set the volume", self.client.volume(volume, None).await),
+            Command::Shuffle(shuffle) => ("change shuffle", self.client.shuffle(shuffle, None).await),
+            Command::Repeat(repeat_state) => ("change repeat", self.client.repeat(repeat_state, None).await),
+            Command::SaveTrack(track_id, save) => {
+                let Ok(track_id) = TrackId::from_id(track_id) else {
+                    return;
+                };
+                if save {
+                    ("like the track", self.client.current_user_saved_tracks_add([track_id]).await)
+                } else {
+                    ("unlike the track", self.client.current_user_saved_tracks_delete([track_id]).await)
+                }
+            }
+            Command::FollowArtist(artist_id, follow) => {
+                let Ok(artist_id) = ArtistId::from_id(artist_id) else {
+                    return;
+                };
+                if follow {
+                    ("follow the artist", self.client.user_follow_artists([artist_id]).await)
+                } else {
+                    ("unfollow the artist", self.client.user_unfollow_artists([artist_id]).await)
+                }
+            }
+        };
+        if let Err(error) = result {
+            self.command_error_tx.send_replace(Some(format!("Could not {action}: {error}")));
+        }
+    }
+
+    /// Wakes the client up again once it gave up after `max_retries`.
+    pub fn retry(&self) -> Arc<Notify> {
+        self.retry.clone()
+    }
+
+    fn update_status(&mut self, state: &StateResult<State>) {
+        // Nothing playing still means spotify answered.
+        if let Err(StateError::Client(_)) = state {
+            self.consecutive_errors += 1;
+        } else {
+            self.consecutive_errors = 0;
+        }
+
+        let status = match self.consecutive_errors {
+            0 => ConnectionStatus::Connected,
+            errors if errors < DISCONNECTED_AFTER_ERRORS => ConnectionStatus::Degraded,
+            _ => ConnectionStatus::Disconnected,
+        };
+        self.status_tx.send_replace(status);
+    }
+
+    /// The account's tier only needs to be looked up once.
+    async fn quality(&mut self) -> Quality {
+        if let Some(quality) = self.quality {
+            return quality;
         }
+
+        let quality = match self.client.current_user().await {
+            Ok(user) => match user.product {
+                Some(SubscriptionLevel::Premium) => Quality::Premium,
+                Some(SubscriptionLevel::Free) => Quality::Free,
+                None => Quality::Unknown,
+            },
+            Err(error) => {
+                eprintln!("Could not look up the account's quality tier: {error}");
+                Quality::Unknown
+            }
+        };
+        self.quality = Some(quality);
+        quality
+    }
+
+    /// Audio features cost an extra request, so they are only fetched once
+    /// per track. Local files have none.
+    async fn features(&mut self, state: &State) -> Option<AudioFeatures> {
+        if state.is_episode {
+            return None;
+        }
+        let track_id = state.track_id.clone()?;
+        if let Some((last_track_id, features)) = &self.last_features {
+            if *last_track_id == track_id {
+                return features.clone();
+            }
+        }
+
+        let features = match self.client.track_features(TrackId::from_id(&track_id).ok()?).await {
+            Ok(features) => Some(features),
+            Err(error) => {
+                eprintln!("Could not fetch audio features: {error}");
+                None
+            }
+        };
+        self.last_features = Some((track_id, features.clone()));
+        features
+    }
+
+    /// Checked once per artist rather than every poll.
+    async fn following_artist(&mut self, state: &State) -> bool {
+        let Some(artist_id) = state.artist_id.clone() else {
+            return false;
+        };
+        if let Some((last_artist_id, following)) = &self.last_follow {
+            if *last_artist_id == artist_id {
+                return *following;
+            }
+        }
+
+        let Ok(id) = ArtistId::from_id(artist_id.as_str()) else {
+            return false;
+        };
+        let following = match self.client.user_artist_check_follow([id]).await {
+            Ok(following) => following.first().copied().unwrap_or(false),
+            Err(error) => {
+                eprintln!("Could not check whether the artist is followed: {error}");
+                false
+            }
+        };
+        self.last_follow = Some((artist_id, following));
+        following
+    }
+
+    /// The album, playlist or artist playback started from, looked up once
+    /// per context.
+    async fn context(&mut self, state: &State) -> Option<ContextInfo> {
+        let uri = state.context_uri.clone()?;
+        if let Some((last_uri, context)) = &self.last_context {
+            if *last_uri == uri {
+                return context.clone();
+            }
+        }
+
+        let context = if let Ok(id) = AlbumId::from_uri(&uri) {
+            self.client.album(id, None).await.map(|album| ContextInfo::named(album.name))
+        } else if let Ok(id) = PlaylistId::from_uri(&uri) {
+            self.client.playlist(id, None, None).await.map(|playlist| ContextInfo {
+                name: playlist.name,
+                is_playlist: true,
+                image_url: playlist.images.first().map(|image| image.url.clone()),
+            })
+        } else if let Ok(id) = ArtistId::from_uri(&uri) {
+            self.client.artist(id).await.map(|artist| ContextInfo::named(artist.name))
+        } else {
+            return None;
+        };
+
+        let mut context = context
+            .map_err(|error| eprintln!("Could not look up the playback context: {error}"))
+            .ok();
+        if let Some(context) = &mut context {
+            if let Some(image_url) = context.image_url.take() {
+                context.image_url = Some(self.cover_art_uri(image_url).await);
+            }
+        }
+        self.last_context = Some((uri, context.clone()));
+        context
+    }
+
+    fn detect_restart(&mut self, state: &mut State) {
+        if let Some((track_id, progress)) = &self.last_position {
+            let jumped_back = *progress - state.progress > chrono::Duration::milliseconds(RESTART_BACKWARD_JUMP_MS);
+            state.restarted = state.track_id.is_some() && *track_id == state.track_id && jumped_back;
+            if *track_id != state.track_id {
+                self.repeat_count = 0;
+            }
+        }
+        if state.restarted && state.repeat_state == RepeatState::Track {
+            self.repeat_count += 1;
+        }
+        state.repeat_count = self.repeat_count;
+        self.last_position = Some((state.track_id.clone(), state.progress));
+    }
+
+    fn notify_change(&mut self, state: &State) {
+        let changed = match &self.last_notified {
+            Some(last) => track_changed(last, state) || last.is_playing != state.is_playing || last.liked != state.liked,
+            None => true,
+        };
+        if !changed {
+            return;
+        }
+        self.last_notified = Some(state.clone());
+
+        let Some(command) = self.config_rx.borrow().on_change_command.clone() else {
+            return;
+        };
+
+        let envs = vec![
+            ("VISIFY_TRACK_ID", state.track_id.clone().unwrap_or_default()),
+            ("VISIFY_TRACK", state.track.clone()),
+            ("VISIFY_ALBUM", state.album.clone()),
+            ("VISIFY_ARTISTS", state.artists.join(", ")),
+            ("VISIFY_IS_PLAYING", state.is_playing.to_string()),
+            ("VISIFY_LIKED", state.liked.to_string()),
+        ];
+        let stdin = serde_json::to_string(&StateSnapshot::from(state)).ok();
+
+        hook::run(&command, envs, stdin);
+    }
+
+    async fn cover_art_uri(&self, url: String) -> String {
+        let Some(cover_cache) = &self.cover_cache else {
+            return url;
+        };
+
+        match cover_cache.fetch(&url).await {
+            Ok(path) => format!("file://{}", path.display()),
+            Err(error) => {
+                eprintln!("{error}");
+                url
+            }
+        }
+    }
+
+    /// Empty without an image, e.g. for local files without artwork.
+    async fn cover_art_url(&self, image: Option<&Image>) -> String {
+        match image {
+            Some(image) => self.cover_art_uri(image.url.clone()).await,
+            None => String::new(),
+        }
+    }
+
+    async fn track_state(
+        &self,
+        track: FullTrack,
+        progress: chrono::Duration,
+        is_playing: bool,
+        shuffled: bool,
+        repeat_state: RepeatState,
+    ) -> StateResult<State> {
+        // Local files have no id and can't be liked.
+        let liked = match track.id.clone() {
+            Some(track_id) => self.client
+                .current_user_saved_tracks_contains([track_id])
+                .await?
+                .first()
+                .copied()
+                .unwrap_or(false),
+            None => false,
+        };
+
+        let duration = track.duration;
+        let instant_of_last_refresh = Instant::now();
+
+        let track_id = track.id.as_ref().map(|id| id.id().to_string());
+        let track_name = track.name.clone();
+        let album = track.album.name.clone();
+        let artists: Vec<String> = track.artists
+            .iter()
+            .map(|artist| artist.name.clone())
+            .collect();
+
+        let artist_id = track.artists.first().and_then(|artist| artist.id.as_ref()).map(|id| id.id().to_string());
+
+        let cover_art_url = self.cover_art_url(track.album.images.first()).await;
+
+        Ok(State {
+            track_id,
+            is_playing,
+            liked,
+            shuffled,
+            repeat_state,
+            progress,
+            duration,
+            instant_of_last_refresh,
+            track: track_name,
+            album,
+            artists,
+            cover_art_url,
+            is_episode: false,
+            restarted: false,
+            repeat_count: 0,
+            quality: Quality::Unknown,
+            mood: None,
+            tempo: None,
+            artist_id,
+            following_artist: false,
+            device: None,
+            volume: None,
+            context_uri: None,
+            context: None,
+            context_image_url: None,
+        })
+    }
+
+    async fn episode_state(
+        &self,
+        episode: FullEpisode,
+        progress: chrono::Duration,
+        is_playing: bool,
+        shuffled: bool,
+        repeat_state: RepeatState,
+    ) -> StateResult<State> {
+        let publisher = if episode.show.publisher.is_empty() {
+            episode.show.name.clone()
+        } else {
+            episode.show.publisher.clone()
+        };
+
+        let cover_art_url = self.cover_art_url(episode.images.first().or(episode.show.images.first())).await;
+
+        Ok(State {
+            track_id: Some(episode.id.id().to_string()),
+            is_playing,
+            shuffled,
+            repeat_state,
+            progress,
+            duration: episode.duration,
+            instant_of_last_refresh: Instant::now(),
+            track: episode.name,
+            album: episode.show.name,
+            artists: vec![publisher],
+            cover_art_url,
+            is_episode: true,
+            ..Default::default()
+        })
     }
 
     async fn get_state(&self) -> StateResult<State>{
-        if let Some(current_playback_context) = self.client.current_playback(None, Some([
-            &AdditionalType::Track,
-            &AdditionalType::Episode
-        ])).await? {
-            if let (Some(progress), Some(PlayableItem::Track(track))) = (current_playback_context.progress, current_playback_context.item) {
-                let liked = self.client
-                    .current_user_saved_tracks_contains([track.id.clone().unwrap()])
-                    .await?
-                    .first()
-                    .unwrap()
-                    .clone();
-                let shuffled = current_playback_context.shuffle_state;
-                let repeat_state = current_playback_context.repeat_state;
-
-                let duration = track.duration;
-                let instant_of_last_refresh = Instant::now();
-
-                let track_name = track.name.clone();
-                let album = track.album.name.clone();
-                let artists: Vec<String> = track.artists
-                    .iter()
-                    .map(|artist| artist.name.clone())
-                    .collect();
-
-                let cover_art_url = track.album.images.first().unwrap().url.clone();
-
-                Ok(State {
-                    liked,
-                    shuffled,
-                    repeat_state,
-                    progress,
-                    duration,
-                    instant_of_last_refresh,
-                    track: track_name,
-                    album,
-                    artists,
-                    cover_art_url,
-                })
-            } else {
-                Err(StateError::MissingState)
+        let additional_types = [&AdditionalType::Track, &AdditionalType::Episode];
+
+        if let Some(current_playback_context) = self.client.current_playback(None, Some(additional_types)).await? {
+            let device = current_playback_context.device.name.clone();
+            let volume = current_playback_context.device.volume_percent.map(|volume| volume.min(100) as u8);
+            let context_uri = current_playback_context.context.as_ref().map(|context| context.uri.clone());
+            let is_playing = current_playback_context.is_playing;
+            let shuffled = current_playback_context.shuffle_state;
+            let repeat_state = current_playback_context.repeat_state;
+            match (current_playback_context.progress, current_playback_context.item) {
+                (Some(progress), Some(PlayableItem::Track(track))) => {
+                    self.track_state(track, progress, is_playing, shuffled, repeat_state).await
+                }
+                (Some(progress), Some(PlayableItem::Episode(episode))) => {
+                    self.episode_state(episode, progress, is_playing, shuffled, repeat_state).await
+                }
+                _ => Err(StateError::MissingState),
+            }.map(|state| State { device: Some(device), volume, context_uri, ..state })
+        } else if self.config_rx.borrow().playing_track_fallback.unwrap() {
+            // The currently-playing endpoint sometimes still reports playback
+            // when no device is active. It doesn't know about shuffle/repeat.
+            match self.client.current_playing(None, Some(additional_types)).await? {
+                Some(CurrentlyPlayingContext {
+                    progress: Some(progress),
+                    item: Some(PlayableItem::Track(track)),
+                    is_playing,
+                    ..
+                }) => self.track_state(track, progress, is_playing, false, RepeatState::Off).await,
+                Some(CurrentlyPlayingContext {
+                    progress: Some(progress),
+                    item: Some(PlayableItem::Episode(episode)),
+                    is_playing,
+                    ..
+                }) => self.episode_state(episode, progress, is_playing, false, RepeatState::Off).await,
+                Some(_) => Err(StateError::MissingState),
+                None => Err(StateError::NoContext),
             }
         } else {
             Err(StateError::NoContext)
         }
     }
 
-    pub fn spawn(self) {
+    pub fn spawn(mut self) {
         tokio::spawn(async move {
-            while let Ok(()) = self.tx.send(self.get_state().await).await {
-                tokio::time::sleep(time::Duration::from_millis(REFRESH_RATE_MS)).await;
+            // The tier rarely changes, one lookup at startup is enough.
+            self.quality().await;
+
+            loop {
+                let refresh_rate_ms = self.config_rx.borrow().refresh_rate_ms.unwrap();
+                let mut state = self.get_state().await;
+                self.update_status(&state);
+                let delay = match &mut state {
+                    Ok(state) => {
+                        self.detect_restart(state);
+                        state.quality = self.quality().await;
+                        let (auto_preset, show_tempo) = {
+                            let config = self.config_rx.borrow();
+                            (config.auto_preset.unwrap(), config.show_tempo.unwrap())
+                        };
+                        let features = if auto_preset || show_tempo { self.features(state).await } else { None };
+                        state.mood = features.as_ref().filter(|_| auto_preset).map(Mood::from_features);
+                        state.tempo = features.filter(|_| show_tempo).map(|features| features.tempo);
+                        state.following_artist = self.following_artist(state).await;
+                        if self.config_rx.borrow().show_header.unwrap() {
+                            if let Some(context) = self.context(state).await {
+                                // Playlists without a custom image show the track art.
+                                state.context_image_url = context.is_playlist
+                                    .then(|| context.image_url.unwrap_or_else(|| state.cover_art_url.clone()))
+                                    .filter(|url| !url.is_empty());
+                                state.context = Some(context.name);
+                            }
+                        }
+                        self.notify_change(state);
+                        next_poll_delay(state, refresh_rate_ms)
+                    }
+                    Err(_) => refresh_rate_ms,
+                };
+
+                if self.tx.send(state).is_err() {
+                    break;
+                }
+
+                let max_retries = self.config_rx.borrow().max_retries;
+                if let Some(max_retries) = max_retries.filter(|max_retries| self.consecutive_errors > *max_retries) {
+                    if self.tx.send(Err(StateError::GaveUp(max_retries))).is_err() {
+                        break;
+                    }
+                    self.retry.notified().await;
+                    self.consecutive_errors = 0;
+                    continue;
+                }
+                // A command polls again right away to pick up its effect.
+                tokio::select! {
+                    _ = tokio::time::sleep(time::Duration::from_millis(delay)) => {}
+                    Some(command) = self.command_rx.recv() => self.run(command).await,
+                }
             }
         });
     }
@@ -0,0 +1,53 @@
+use std::{fs, path::{Path, PathBuf}, process, time::{Duration, SystemTime}};
+
+use visify::cache::{cache_key, evict};
+
+fn cache_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("visify-test-{}-{name}", process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Writes a 10 byte file last used `age_secs` ago.
+fn cached_file(dir: &Path, name: &str, age_secs: u64) -> PathBuf {
+    let path = dir.join(name);
+    fs::write(&path, [0; 10]).unwrap();
+    let modified = SystemTime::now() - Duration::from_secs(age_secs);
+    fs::File::options().append(true).open(&path).unwrap().set_modified(modified).unwrap();
+    path
+}
+
+#[test]
+fn keys_stay_the_same_across_builds() {
+    assert_eq!(cache_key("https://i.scdn.co/image/ab67616d0000b273"), "dc761bfa6b9bf2cb");
+    assert_eq!(cache_key(""), "cbf29ce484222325");
+}
+
+#[test]
+fn least_recently_used_files_go_first() {
+    let dir = cache_dir("lru");
+    let oldest = cached_file(&dir, "oldest", 300);
+    let older = cached_file(&dir, "older", 200);
+    let recent = cached_file(&dir, "recent", 100);
+    let fresh = cached_file(&dir, "fresh", 0);
+
+    evict(&dir, 20, &fresh).unwrap();
+    assert!(!oldest.exists());
+    assert!(!older.exists());
+    assert!(recent.exists());
+    assert!(fresh.exists());
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn fresh_file_is_kept_even_when_it_is_the_oldest() {
+    let dir = cache_dir("fresh");
+    let fresh = cached_file(&dir, "fresh", 300);
+    let other = cached_file(&dir, "other", 100);
+
+    evict(&dir, 10, &fresh).unwrap();
+    assert!(fresh.exists());
+    assert!(!other.exists());
+    fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,113 @@
+use std::{fs, path::PathBuf, process};
+
+use visify::config::{Config, BandSpacing, LayoutMode, ProgressStyle, MIN_REFRESH_RATE_MS};
+
+fn config_path(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("visify-test-{}-{name}.yml", process::id()));
+    let _ = fs::remove_file(&path);
+    path
+}
+
+#[test]
+fn missing_file_loads_defaults() {
+    let path = config_path("missing");
+    let config = Config::load_from(&path).unwrap();
+
+    assert_eq!(config.redirect_uri_port, Some(8888));
+    assert_eq!(config.reference_pitch, Some(440.0));
+    assert_eq!(config.progress_style, Some(ProgressStyle::Bar));
+    assert_eq!(config.band_spacing, None);
+    fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn unset_port_is_filled_after_round_trip() {
+    let path = config_path("default-port");
+    let config = Config {
+        redirect_uri_port: None,
+        ..Default::default()
+    };
+    config.store_to(&path).unwrap();
+
+    let config = Config::load_from(&path).unwrap();
+    assert_eq!(config.redirect_uri_port, Some(8888));
+    fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn custom_values_survive_round_trip() {
+    let path = config_path("custom");
+    let config = Config {
+        redirect_uri_port: Some(9001),
+        band_spacing: Some(BandSpacing::MusicalNotes),
+        reference_pitch: Some(432.0),
+        layout: Some(LayoutMode::MiniProgress),
+        scopes: Some(vec!["user-read-playback-state".to_string()]),
+        lyrics_dir: Some(PathBuf::from("/tmp/lyrics")),
+        ..Default::default()
+    };
+    config.store_to(&path).unwrap();
+
+    let config = Config::load_from(&path).unwrap();
+    assert_eq!(config.redirect_uri_port, Some(9001));
+    assert_eq!(config.band_spacing, Some(BandSpacing::MusicalNotes));
+    assert_eq!(config.reference_pitch, Some(432.0));
+    assert_eq!(config.layout, Some(LayoutMode::MiniProgress));
+    assert_eq!(config.scopes, Some(vec!["user-read-playback-state".to_string()]));
+    assert_eq!(config.lyrics_dir, Some(PathBuf::from("/tmp/lyrics")));
+    fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn defaults_fill_every_defaulted_setting() {
+    let config = Config::defaults();
+
+    assert_eq!(config.redirect_uri_port, Some(8888));
+    assert_eq!(config.window_opacity, Some(1.0));
+    assert!(config.client_id.is_some());
+    assert!(config.scopes.is_some_and(|scopes| !scopes.is_empty()));
+    assert_eq!(config.gravity, None);
+}
+
+#[test]
+fn fresh_file_stays_empty() {
+    let path = config_path("fresh");
+    let config = Config::load_from(&path).unwrap();
+    assert_eq!(config.redirect_uri_port, Some(8888));
+
+    let stored: Config = serde_yaml::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+    assert_eq!(stored.client_id, None);
+    assert_eq!(stored.redirect_uri_port, None);
+    assert_eq!(stored.scopes, None);
+    assert_eq!(stored.decorated, None);
+    fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn refresh_rate_is_kept_above_the_minimum() {
+    let path = config_path("refresh-rate");
+    let config = Config {
+        refresh_rate_ms: Some(10),
+        ..Default::default()
+    };
+    config.store_to(&path).unwrap();
+
+    let config = Config::load_from(&path).unwrap();
+    assert_eq!(config.refresh_rate_ms, Some(MIN_REFRESH_RATE_MS));
+    assert_eq!(Config::defaults().refresh_rate_ms, Some(5000));
+    fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn smoothing_stays_below_one() {
+    let path = config_path("smoothing");
+    let config = Config {
+        smoothing: Some(1.5),
+        ..Default::default()
+    };
+    config.store_to(&path).unwrap();
+
+    let config = Config::load_from(&path).unwrap();
+    assert!(config.smoothing.is_some_and(|smoothing| smoothing < 1.0));
+    fs::remove_file(path).unwrap();
+}
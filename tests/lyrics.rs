@@ -0,0 +1,47 @@
+use chrono::Duration;
+use visify::lyrics::Lyrics;
+
+fn at(lyrics: &Lyrics, millis: i64) -> Option<&str> {
+    lyrics.line_at(Duration::milliseconds(millis))
+}
+
+#[test]
+fn line_with_several_timestamps_repeats() {
+    let lyrics = Lyrics::parse("[00:10.00]verse\n[00:20.00][01:00.00]chorus\n[00:30.00]bridge");
+
+    assert_eq!(at(&lyrics, 25_000), Some("chorus"));
+    assert_eq!(at(&lyrics, 35_000), Some("bridge"));
+    assert_eq!(at(&lyrics, 61_000), Some("chorus"));
+}
+
+#[test]
+fn unsorted_lines_are_put_in_order() {
+    let lyrics = Lyrics::parse("[00:30.00]third\n[00:10.00]first\n[00:20.00]second");
+
+    assert_eq!(at(&lyrics, 15_000), Some("first"));
+    assert_eq!(at(&lyrics, 25_000), Some("second"));
+    assert_eq!(at(&lyrics, 35_000), Some("third"));
+}
+
+#[test]
+fn hundredths_are_optional() {
+    let lyrics = Lyrics::parse("[00:10]whole\n[00:10.50]fraction");
+
+    assert_eq!(at(&lyrics, 10_000), Some("whole"));
+    assert_eq!(at(&lyrics, 10_499), Some("whole"));
+    assert_eq!(at(&lyrics, 10_500), Some("fraction"));
+}
+
+#[test]
+fn nothing_before_the_first_line() {
+    let lyrics = Lyrics::parse("[00:10.00]first");
+    assert_eq!(at(&lyrics, 9_999), None);
+}
+
+#[test]
+fn junk_and_metadata_lines_are_skipped() {
+    let lyrics = Lyrics::parse("[ar:Artist]\n[offset:+500]\nno tag at all\n[00:1x.00]bad\n[00:05.00\n\n[00:10.00]real");
+
+    assert_eq!(at(&lyrics, 9_000), None);
+    assert_eq!(at(&lyrics, 10_000), Some("real"));
+}
@@ -0,0 +1,19 @@
+use visify::state::{Command, Quality};
+
+#[test]
+fn free_accounts_cannot_control_playback() {
+    assert!(!Quality::Free.can_control_playback());
+    assert!(Quality::Premium.can_control_playback());
+}
+
+#[test]
+fn unknown_tier_is_given_the_benefit_of_the_doubt() {
+    assert!(Quality::Unknown.can_control_playback());
+}
+
+#[test]
+fn library_changes_do_not_need_playback_control() {
+    assert!(!Command::SaveTrack("id".to_string(), true).controls_playback());
+    assert!(Command::Pause.controls_playback());
+    assert!(Command::Volume(50).controls_playback());
+}
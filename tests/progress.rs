@@ -0,0 +1,85 @@
+use visify::{format_duration, progress_fraction, seek_position, config::MIN_REFRESH_RATE_MS, state::{next_poll_delay, State}};
+
+#[test]
+fn formats_short_durations() {
+    assert_eq!(format_duration(chrono::Duration::zero()), "00:00");
+    assert_eq!(format_duration(chrono::Duration::milliseconds(400)), "00:00");
+    assert_eq!(format_duration(chrono::Duration::seconds(7)), "00:07");
+    assert_eq!(format_duration(chrono::Duration::seconds(59)), "00:59");
+    assert_eq!(format_duration(chrono::Duration::seconds(5 * 60 + 3)), "05:03");
+}
+
+#[test]
+fn formats_durations_around_an_hour() {
+    assert_eq!(format_duration(chrono::Duration::seconds(59 * 60 + 59)), "59:59");
+    assert_eq!(format_duration(chrono::Duration::hours(1)), "1:00:00");
+    assert_eq!(format_duration(chrono::Duration::seconds(3600 + 30 * 60 + 15)), "1:30:15");
+}
+
+#[test]
+fn formats_multi_hour_durations() {
+    assert_eq!(format_duration(chrono::Duration::seconds(12 * 3600 + 5 * 60 + 9)), "12:05:09");
+    assert_eq!(format_duration(chrono::Duration::hours(100)), "100:00:00");
+}
+
+#[test]
+fn fraction_of_zero_duration_is_zero() {
+    assert_eq!(progress_fraction(chrono::Duration::zero(), chrono::Duration::zero()), 0.);
+    assert_eq!(progress_fraction(chrono::Duration::seconds(3), chrono::Duration::zero()), 0.);
+}
+
+#[test]
+fn fraction_of_sub_second_duration_is_finite() {
+    let fraction = progress_fraction(chrono::Duration::milliseconds(250), chrono::Duration::milliseconds(500));
+    assert_eq!(fraction, 0.5);
+
+    let fraction = progress_fraction(chrono::Duration::milliseconds(1), chrono::Duration::microseconds(500));
+    assert!(fraction.is_finite());
+}
+
+#[test]
+fn fraction_is_clamped() {
+    let duration = chrono::Duration::seconds(8);
+    assert_eq!(progress_fraction(chrono::Duration::seconds(12), duration), 1.);
+    assert_eq!(progress_fraction(chrono::Duration::seconds(-1), duration), 0.);
+}
+
+#[test]
+fn seeking_without_duration_does_nothing() {
+    assert_eq!(seek_position(0.5, chrono::Duration::zero()), None);
+}
+
+#[test]
+fn seek_position_is_clamped() {
+    let duration = chrono::Duration::seconds(8);
+    assert_eq!(seek_position(0.5, duration), Some(chrono::Duration::seconds(4)));
+    assert_eq!(seek_position(1.5, duration), Some(duration));
+    assert_eq!(seek_position(-0.5, duration), Some(chrono::Duration::zero()));
+}
+
+fn playing(duration_ms: i64, progress_ms: i64) -> State {
+    State {
+        is_playing: true,
+        duration: chrono::Duration::milliseconds(duration_ms),
+        progress: chrono::Duration::milliseconds(progress_ms),
+        ..Default::default()
+    }
+}
+
+#[test]
+fn paused_tracks_poll_at_the_refresh_rate() {
+    let state = State { is_playing: false, ..playing(3000, 2900) };
+    assert_eq!(next_poll_delay(&state, 5000), 5000);
+}
+
+#[test]
+fn polls_again_just_after_the_track_ends() {
+    assert_eq!(next_poll_delay(&playing(10_000, 8000), 5000), 2250);
+    assert_eq!(next_poll_delay(&playing(60_000, 0), 5000), 5000);
+}
+
+#[test]
+fn track_end_polls_stay_above_the_minimum() {
+    assert_eq!(next_poll_delay(&playing(1000, 1000), 5000), MIN_REFRESH_RATE_MS);
+    assert_eq!(next_poll_delay(&playing(1000, 1500), 5000), MIN_REFRESH_RATE_MS);
+}
@@ -0,0 +1,137 @@
+use visify::{config::BandSpacing, spectrum::{band_edges, fall_under_gravity, note_name, normalization_peak, resample, spectrum_to_bands}};
+
+const SPECTRUM: [(f64, f64); 4] = [(20.0, 8.0), (100.0, 2.0), (1000.0, 3.0), (15000.0, 6.0)];
+
+#[test]
+fn peak_is_the_loudest_magnitude() {
+    assert_eq!(normalization_peak(&SPECTRUM, None, None), 8.0);
+}
+
+#[test]
+fn excluded_ranges_do_not_count() {
+    assert_eq!(normalization_peak(&SPECTRUM, Some(50.0), None), 6.0);
+    assert_eq!(normalization_peak(&SPECTRUM, Some(50.0), Some(10000.0)), 3.0);
+}
+
+#[test]
+fn exclusion_bounds_are_inclusive() {
+    assert_eq!(normalization_peak(&SPECTRUM, Some(100.0), Some(100.0)), 2.0);
+}
+
+#[test]
+fn everything_excluded_peaks_at_zero() {
+    assert_eq!(normalization_peak(&SPECTRUM, Some(2000.0), Some(1000.0)), 0.0);
+}
+
+fn assert_close(actual: f64, expected: f64) {
+    assert!((actual - expected).abs() < 1e-6, "{actual} is not {expected}");
+}
+
+#[test]
+fn logarithmic_edges_cover_the_range_evenly() {
+    let edges = band_edges(BandSpacing::Logarithmic, 20.0, 20000.0, 440.0);
+
+    assert!(edges[0] <= 20.0);
+    assert!(*edges.last().unwrap() >= 20000.0);
+    for pair in edges.windows(2) {
+        assert_close(pair[1] / pair[0], 2.0_f64.powf(1.0 / 6.0));
+    }
+}
+
+#[test]
+fn note_bands_are_centered_on_the_reference_pitch() {
+    for reference_pitch in [440.0, 432.0] {
+        let edges = band_edges(BandSpacing::MusicalNotes, reference_pitch - 5.0, reference_pitch + 5.0, reference_pitch);
+        let bands = spectrum_to_bands(&[(reference_pitch, 1.0)], &edges);
+
+        assert_eq!(bands.len(), 1);
+        assert_close(bands[0].0, reference_pitch);
+    }
+}
+
+#[test]
+fn bands_keep_the_loudest_bin() {
+    let edges = [100.0, 200.0, 400.0];
+    let bands = spectrum_to_bands(&[(110.0, 1.0), (150.0, 4.0), (190.0, 2.0), (300.0, 3.0)], &edges);
+
+    assert_eq!(bands.iter().map(|(_, magnitude)| *magnitude).collect::<Vec<_>>(), [4.0, 3.0]);
+}
+
+#[test]
+fn empty_bands_are_dropped() {
+    let edges = [100.0, 200.0, 400.0, 800.0];
+    let bands = spectrum_to_bands(&[(150.0, 1.0), (500.0, 2.0)], &edges);
+
+    assert_eq!(bands.len(), 2);
+    assert_close(bands[0].0, (100.0_f64 * 200.0).sqrt());
+    assert_close(bands[1].0, (400.0_f64 * 800.0).sqrt());
+}
+
+#[test]
+fn bands_beyond_nyquist_are_dropped() {
+    // At 2 kHz sampling there are no bins above 1 kHz.
+    let spectrum = [(250.0, 1.0), (500.0, 1.0), (1000.0, 1.0)];
+    let edges = band_edges(BandSpacing::Logarithmic, 200.0, 8000.0, 440.0);
+    let bands = spectrum_to_bands(&spectrum, &edges);
+
+    assert!(!bands.is_empty());
+    assert!(bands.iter().all(|(center, _)| *center < 1100.0));
+}
+
+#[test]
+fn notes_are_named_relative_to_the_reference_pitch() {
+    assert_eq!(note_name(440.0, 440.0), "A4");
+    assert_eq!(note_name(261.63, 440.0), "C4");
+    assert_eq!(note_name(16.35, 440.0), "C0");
+    assert_eq!(note_name(30.87, 440.0), "B0");
+    assert_eq!(note_name(432.0, 432.0), "A4");
+    assert_eq!(note_name(445.0, 440.0), "A4");
+}
+
+#[test]
+fn gravity_jumps_straight_up() {
+    let mut velocity = 3.0;
+    assert_eq!(fall_under_gravity(50.0, 10.0, &mut velocity, 10.0, 0.1), 50.0);
+    assert_eq!(velocity, 0.0);
+}
+
+#[test]
+fn gravity_falls_faster_and_faster() {
+    let mut velocity = 0.0;
+    let once = fall_under_gravity(1.0, 100.0, &mut velocity, 10.0, 0.1);
+    assert_close(once.log10(), 1.9);
+
+    let twice = fall_under_gravity(1.0, once, &mut velocity, 10.0, 0.1);
+    assert_close(twice.log10(), 1.7);
+}
+
+#[test]
+fn gravity_stops_at_the_new_level() {
+    let mut velocity = 0.0;
+    assert_eq!(fall_under_gravity(1.0, 100.0, &mut velocity, 10.0, 1.0), 1.0);
+    assert_eq!(velocity, 0.0);
+}
+
+#[test]
+fn resampling_keeps_peaks() {
+    let points = [(0.0, 1.0), (0.5, 5.0), (1.5, 2.0), (2.5, 3.0), (3.5, 1.0)];
+    assert_eq!(resample(&points, 0.0, 4.0, 2), [(1.0, 5.0), (3.0, 3.0)]);
+}
+
+#[test]
+fn empty_columns_are_interpolated() {
+    let points = [(0.0, 0.0), (4.0, 4.0)];
+    assert_eq!(resample(&points, 0.0, 4.0, 4), [(0.5, 0.0), (1.5, 1.5), (2.5, 2.5), (3.5, 3.5)]);
+}
+
+#[test]
+fn columns_past_the_last_point_hold_its_level() {
+    let points = [(0.0, 1.0), (1.0, 2.0)];
+    assert_eq!(resample(&points, 0.0, 4.0, 4), [(0.5, 1.0), (1.5, 2.0), (2.5, 2.0), (3.5, 2.0)]);
+}
+
+#[test]
+fn too_little_to_resample_is_left_alone() {
+    assert_eq!(resample(&[(1.0, 1.0)], 0.0, 4.0, 4), [(1.0, 1.0)]);
+    assert_eq!(resample(&[(1.0, 1.0), (2.0, 2.0)], 0.0, 4.0, 0), [(1.0, 1.0), (2.0, 2.0)]);
+}
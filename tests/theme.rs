@@ -0,0 +1,36 @@
+use eframe::epaint::Color32;
+use visify::{parse_hex_color, spectrum::adjust_color};
+
+#[test]
+fn parses_hex_colors_with_or_without_hash() {
+    assert_eq!(parse_hex_color("#c42727"), Some(Color32::from_rgb(196, 39, 39)));
+    assert_eq!(parse_hex_color("7A2427"), Some(Color32::from_rgb(122, 36, 39)));
+}
+
+#[test]
+fn rejects_malformed_hex_colors() {
+    assert_eq!(parse_hex_color("#c4272"), None);
+    assert_eq!(parse_hex_color("#gggggg"), None);
+    assert_eq!(parse_hex_color("red"), None);
+    assert_eq!(parse_hex_color("#ééé"), None);
+}
+
+#[test]
+fn neutral_adjustment_keeps_the_color() {
+    let color = Color32::from_rgb(196, 39, 39);
+    assert_eq!(adjust_color(color, 1.0, 1.0), color);
+}
+
+#[test]
+fn adjustment_keeps_alpha_and_clamps_channels() {
+    let color = Color32::from_rgba_unmultiplied(200, 100, 0, 128);
+
+    assert_eq!(adjust_color(color, 0.0, 1.0), Color32::from_rgba_unmultiplied(0, 0, 0, 128));
+    assert_eq!(adjust_color(color, 2.0, 1.0).to_srgba_unmultiplied()[0], 255);
+}
+
+#[test]
+fn zero_contrast_turns_grey() {
+    let color = Color32::from_rgb(196, 39, 39);
+    assert_eq!(adjust_color(color, 1.0, 0.0), Color32::from_rgb(128, 128, 128));
+}
@@ -0,0 +1,53 @@
+use visify::state::{track_changed, State};
+
+fn track(id: Option<&str>, name: &str, artists: &[&str]) -> State {
+    State {
+        track_id: id.map(String::from),
+        track: name.to_string(),
+        artists: artists.iter().map(|artist| artist.to_string()).collect(),
+        ..Default::default()
+    }
+}
+
+#[test]
+fn same_track_is_not_a_change() {
+    let old = track(Some("a"), "Song", &["Artist"]);
+    let new = track(Some("a"), "Song", &["Artist"]);
+    assert!(!track_changed(&old, &new));
+}
+
+#[test]
+fn different_id_is_a_change() {
+    let old = track(Some("a"), "Song", &["Artist"]);
+    let new = track(Some("b"), "Song", &["Artist"]);
+    assert!(track_changed(&old, &new));
+}
+
+#[test]
+fn first_track_after_nothing_is_a_change() {
+    let new = track(Some("a"), "Song", &["Artist"]);
+    assert!(track_changed(&State::default(), &new));
+}
+
+#[test]
+fn local_tracks_compare_by_name_and_artists() {
+    let old = track(None, "Demo", &["Band"]);
+    assert!(!track_changed(&old, &track(None, "Demo", &["Band"])));
+    assert!(track_changed(&old, &track(None, "Demo 2", &["Band"])));
+    assert!(track_changed(&old, &track(None, "Demo", &["Other Band"])));
+}
+
+#[test]
+fn switching_between_local_and_streamed_is_a_change() {
+    let local = track(None, "Song", &["Artist"]);
+    let streamed = track(Some("a"), "Song", &["Artist"]);
+    assert!(track_changed(&local, &streamed));
+    assert!(track_changed(&streamed, &local));
+}
+
+#[test]
+fn restart_on_repeat_one_is_a_change() {
+    let old = track(Some("a"), "Song", &["Artist"]);
+    let new = State { restarted: true, ..track(Some("a"), "Song", &["Artist"]) };
+    assert!(track_changed(&old, &new));
+}